@@ -0,0 +1,611 @@
+//! `cli` holds the command line definition of `minigrep`.
+//!
+//! The options are described once in [`OPTIONS`] and [`ARGS`].
+//! The parser in [`Command::parse`], the help text, the shell
+//! completion scripts and the man page are all generated from
+//! that table, so the documentation never drifts from the parser.
+//!
+//! The hidden `--generate <TARGET>` option prints one of the
+//! generated documents:
+//!
+//! ```bash
+//! minigrep --generate bash > /etc/bash_completion.d/minigrep
+//! minigrep --generate man > minigrep.1
+//! ```
+
+use crate::{Case, Config, Normalization, Sort};
+
+/// the program name used in the generated documents
+pub const NAME: &str = "minigrep";
+
+/// describes a command line option.
+#[derive(Debug)]
+pub struct Opt {
+    /// the short form, e.g. `h` for `-h`
+    pub short: Option<char>,
+    /// the long form, e.g. `help` for `--help`
+    pub long: &'static str,
+    /// the name of the option's value, `None` for a flag
+    pub value: Option<&'static str>,
+    /// the possible values of the option, used for completion
+    pub choices: &'static [&'static str],
+    /// one line description of the option
+    pub help: &'static str,
+    /// hidden options are left out of the help and documents
+    pub hidden: bool,
+}
+
+/// all options accepted by `minigrep`.
+pub const OPTIONS: &[Opt] = &[
+    Opt {
+        short: Some('h'),
+        long: "help",
+        value: None,
+        choices: &[],
+        help: "print help information",
+        hidden: false,
+    },
+    Opt {
+        short: Some('i'),
+        long: "ignore-case",
+        value: None,
+        choices: &[],
+        help: "search case-insensitively",
+        hidden: false,
+    },
+    Opt {
+        short: Some('S'),
+        long: "smart-case",
+        value: None,
+        choices: &[],
+        help: "search case-insensitively unless the query contains uppercase",
+        hidden: false,
+    },
+    Opt {
+        short: None,
+        long: "normalize",
+        value: Some("FORM"),
+        choices: &["nfc", "nfkc"],
+        help: "normalize query and text to the Unicode FORM before matching",
+        hidden: false,
+    },
+    Opt {
+        short: None,
+        long: "sort",
+        value: Some("KEY"),
+        choices: &["path", "modified"],
+        help: "search files in order of their path or modification time",
+        hidden: false,
+    },
+    Opt {
+        short: None,
+        long: "stats",
+        value: None,
+        choices: &[],
+        help: "print per-file statistics to stderr when done",
+        hidden: false,
+    },
+    Opt {
+        short: Some('l'),
+        long: "files-with-matches",
+        value: None,
+        choices: &[],
+        help: "print only the names of files with matches",
+        hidden: false,
+    },
+    Opt {
+        short: Some('0'),
+        long: "null",
+        value: None,
+        choices: &[],
+        help: "terminate file names with NUL, e.g. for xargs -0",
+        hidden: false,
+    },
+    Opt {
+        short: None,
+        long: "null-data",
+        value: None,
+        choices: &[],
+        help: "treat input and output records as NUL-separated",
+        hidden: false,
+    },
+    Opt {
+        short: None,
+        long: "generate",
+        value: Some("TARGET"),
+        choices: Target::NAMES,
+        help: "print a shell completion script or the man page",
+        hidden: true,
+    },
+];
+
+/// positional arguments with their descriptions, in order.
+/// The last one may be repeated.
+pub const ARGS: &[(&str, &str)] = &[
+    ("QUERY", "the string to search"),
+    (
+        "FILE",
+        "the files to search from, directories are searched recursively, - for stdin",
+    ),
+];
+
+/// one of the documents generated from the option table.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Target {
+    /// bash completion script
+    Bash,
+    /// zsh completion script
+    Zsh,
+    /// fish completion script
+    Fish,
+    /// roff man page
+    Man,
+}
+
+impl Target {
+    const NAMES: &'static [&'static str] = &["bash", "zsh", "fish", "man"];
+
+    fn from_name(name: &str) -> Option<Target> {
+        match name {
+            "bash" => Some(Target::Bash),
+            "zsh" => Some(Target::Zsh),
+            "fish" => Some(Target::Fish),
+            "man" => Some(Target::Man),
+            _ => None,
+        }
+    }
+}
+
+/// what the user asks `minigrep` to do.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    /// search with the given config
+    Search(Config),
+    /// print the help information
+    Help,
+    /// print a generated document
+    Generate(Target),
+}
+
+pub(crate) const ERROR_MISS_QUERY: &str = "miss query string in the arguments";
+pub(crate) const ERROR_MISS_FILEPATH: &str = "miss filepath in the arguments";
+pub(crate) const ERROR_UNKNOWN_OPTION: &str = "unknown option in the arguments";
+pub(crate) const ERROR_MISS_VALUE: &str = "miss value of an option in the arguments";
+pub(crate) const ERROR_INVALID_VALUE: &str = "invalid value of an option in the arguments";
+pub(crate) const ERROR_UNEXPECTED_VALUE: &str = "unexpected value of a flag in the arguments";
+
+impl Command {
+    /// Parses the command line, including the program name.
+    ///
+    /// Options may appear anywhere; `--` ends the options.
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Command, &'static str> {
+        args.next();
+
+        let mut positionals = Vec::new();
+        let mut only_positionals = false;
+        let mut help = false;
+        let mut generate = None;
+        let mut config = Config::default();
+
+        while let Some(arg) = args.next() {
+            if only_positionals || arg == "-" || !arg.starts_with('-') {
+                positionals.push(arg);
+                continue;
+            }
+            if arg == "--" {
+                only_positionals = true;
+                continue;
+            }
+
+            for (opt, inline) in lookup(&arg)? {
+                let value = match (opt.value, inline) {
+                    (None, Some(_)) => return Err(ERROR_UNEXPECTED_VALUE),
+                    (None, None) => None,
+                    (Some(_), Some(v)) => Some(v),
+                    (Some(_), None) => Some(args.next().ok_or(ERROR_MISS_VALUE)?),
+                };
+
+                // the last of the case options wins
+                match opt.long {
+                    "help" => help = true,
+                    "ignore-case" => config.case = Case::Insensitive,
+                    "smart-case" => config.case = Case::Smart,
+                    "normalize" => {
+                        config.normalization = match value.as_deref() {
+                            Some("nfc") => Some(Normalization::Nfc),
+                            Some("nfkc") => Some(Normalization::Nfkc),
+                            _ => return Err(ERROR_INVALID_VALUE),
+                        }
+                    }
+                    "sort" => {
+                        config.sort = match value.as_deref() {
+                            Some("path") => Some(Sort::Path),
+                            Some("modified") => Some(Sort::Modified),
+                            _ => return Err(ERROR_INVALID_VALUE),
+                        }
+                    }
+                    "stats" => config.stats = true,
+                    "files-with-matches" => config.files_with_matches = true,
+                    "null" => config.null = true,
+                    "null-data" => config.null_data = true,
+                    "generate" => {
+                        let value = value.unwrap_or_default();
+                        generate = Some(Target::from_name(&value).ok_or(ERROR_INVALID_VALUE)?);
+                    }
+                    _ => unreachable!("option --{} is not handled", opt.long),
+                }
+            }
+        }
+
+        if help {
+            return Ok(Command::Help);
+        }
+        if let Some(target) = generate {
+            return Ok(Command::Generate(target));
+        }
+
+        let mut positionals = positionals.into_iter();
+        config.query = positionals.next().ok_or(ERROR_MISS_QUERY)?;
+        config.file_paths = positionals.collect();
+        if config.file_paths.is_empty() {
+            return Err(ERROR_MISS_FILEPATH);
+        }
+
+        Ok(Command::Search(config))
+    }
+}
+
+/// resolves one argument into options, together with any value
+/// given inline, as in `--generate=bash`.
+fn lookup(arg: &str) -> Result<Vec<(&'static Opt, Option<String>)>, &'static str> {
+    if let Some(long) = arg.strip_prefix("--") {
+        let (name, inline) = match long.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (long, None),
+        };
+        let opt = OPTIONS
+            .iter()
+            .find(|o| o.long == name)
+            .ok_or(ERROR_UNKNOWN_OPTION)?;
+        return Ok(vec![(opt, inline)]);
+    }
+
+    // short flags may be grouped as in `-ab`; an option taking a
+    // value consumes the rest of the group.
+    let mut opts = Vec::new();
+    for (i, c) in arg[1..].char_indices() {
+        let opt = OPTIONS
+            .iter()
+            .find(|o| o.short == Some(c))
+            .ok_or(ERROR_UNKNOWN_OPTION)?;
+        if opt.value.is_some() {
+            let rest = &arg[1 + i + c.len_utf8()..];
+            opts.push((opt, (!rest.is_empty()).then(|| rest.to_string())));
+            break;
+        }
+        opts.push((opt, None));
+    }
+    Ok(opts)
+}
+
+/// marks the last positional argument as repeatable.
+fn repeated(i: usize) -> &'static str {
+    if i + 1 == ARGS.len() {
+        "..."
+    } else {
+        ""
+    }
+}
+
+fn visible() -> impl Iterator<Item = &'static Opt> {
+    OPTIONS.iter().filter(|o| !o.hidden)
+}
+
+fn synopsis(opt: &Opt) -> String {
+    let mut s = match opt.short {
+        Some(c) => format!("-{c}, --{}", opt.long),
+        None => format!("    --{}", opt.long),
+    };
+    if let Some(v) = opt.value {
+        s.push_str(&format!(" <{v}>"));
+    }
+    s
+}
+
+/// the one line usage of the program.
+pub fn usage() -> String {
+    let args: Vec<_> = ARGS
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _))| format!("<{name}>{}", repeated(i)))
+        .collect();
+    format!("Usage: {NAME} [OPTIONS] {}", args.join(" "))
+}
+
+/// the help information printed by `--help`.
+pub fn help() -> String {
+    let mut s = format!(
+        "{NAME} - search a string from a text file\n\n{}\n\nArguments:\n",
+        usage()
+    );
+    let width = ARGS
+        .iter()
+        .map(|(name, _)| name.len() + 2)
+        .max()
+        .unwrap_or(0);
+    for (name, help) in ARGS {
+        s.push_str(&format!("  {:width$}  {help}\n", format!("<{name}>")));
+    }
+    s.push_str("\nOptions:\n");
+    let width = visible().map(|o| synopsis(o).len()).max().unwrap_or(0);
+    for opt in visible() {
+        s.push_str(&format!("  {:width$}  {}\n", synopsis(opt), opt.help));
+    }
+    s
+}
+
+/// generates the document for the given target.
+pub fn generate(target: Target) -> String {
+    match target {
+        Target::Bash => bash(),
+        Target::Zsh => zsh(),
+        Target::Fish => fish(),
+        Target::Man => man(),
+    }
+}
+
+fn bash() -> String {
+    let mut words = Vec::new();
+    let mut cases = String::new();
+    for opt in visible() {
+        let mut forms = Vec::new();
+        if let Some(c) = opt.short {
+            forms.push(format!("-{c}"));
+        }
+        forms.push(format!("--{}", opt.long));
+        if opt.value.is_some() {
+            let reply = match opt.choices {
+                [] => "COMPREPLY=($(compgen -f -- \"$cur\"))".to_string(),
+                choices => format!(
+                    "COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+                    choices.join(" ")
+                ),
+            };
+            cases.push_str(&format!(
+                "        {})\n            {reply}\n            return 0\n            ;;\n",
+                forms.join("|")
+            ));
+        }
+        words.extend(forms);
+    }
+
+    format!(
+        "_{NAME}() {{
+    local cur prev
+    cur=\"${{COMP_WORDS[COMP_CWORD]}}\"
+    prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"
+
+    case \"$prev\" in
+{cases}    esac
+
+    if [[ \"$cur\" == -* ]]; then
+        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))
+    else
+        COMPREPLY=($(compgen -f -- \"$cur\"))
+    fi
+}}
+complete -F _{NAME} {NAME}
+",
+        words.join(" ")
+    )
+}
+
+fn zsh() -> String {
+    let mut specs = Vec::new();
+    for opt in visible() {
+        let help = opt
+            .help
+            .replace('\'', "'\\''")
+            .replace('[', "\\[")
+            .replace(']', "\\]");
+        let action = match (opt.value, opt.choices) {
+            (None, _) => String::new(),
+            (Some(v), []) => format!(":{v}:_files"),
+            (Some(v), choices) => format!(":{v}:({})", choices.join(" ")),
+        };
+        match opt.short {
+            Some(c) => specs.push(format!(
+                "'(-{c} --{long})'{{-{c},--{long}}}'[{help}]{action}'",
+                long = opt.long
+            )),
+            None => specs.push(format!("'--{}[{help}]{action}'", opt.long)),
+        }
+    }
+    for (i, (name, help)) in ARGS.iter().enumerate() {
+        let action = if i == 0 { " " } else { "_files" };
+        let position = match repeated(i) {
+            "" => (i + 1).to_string(),
+            _ => "*".to_string(),
+        };
+        specs.push(format!(
+            "'{position}:{} - {help}:{action}'",
+            name.to_lowercase()
+        ));
+    }
+
+    format!(
+        "#compdef {NAME}\n\n_arguments -s \\\n    {}\n",
+        specs.join(" \\\n    ")
+    )
+}
+
+fn fish() -> String {
+    let mut s = String::new();
+    for opt in visible() {
+        let mut line = format!("complete -c {NAME}");
+        if let Some(c) = opt.short {
+            line.push_str(&format!(" -s {c}"));
+        }
+        line.push_str(&format!(" -l {}", opt.long));
+        if opt.value.is_some() {
+            line.push_str(" -r");
+            if !opt.choices.is_empty() {
+                line.push_str(&format!(" -f -a '{}'", opt.choices.join(" ")));
+            }
+        }
+        line.push_str(&format!(" -d '{}'\n", opt.help.replace('\'', "\\'")));
+        s.push_str(&line);
+    }
+    s
+}
+
+/// escapes text for roff, where `-` is a hyphen and `\` starts an escape.
+fn roff(text: &str) -> String {
+    text.replace('\\', "\\e").replace('-', "\\-")
+}
+
+fn man() -> String {
+    let args: Vec<_> = ARGS
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _))| format!("\\fI{name}\\fR{}", repeated(i)))
+        .collect();
+    let mut s = format!(
+        ".TH {} 1 \"\" \"{NAME} {}\"\n.SH NAME\n{NAME} \\- search a string from a text file\n.SH SYNOPSIS\n\\fB{NAME}\\fR [\\fIOPTIONS\\fR] {}\n.SH DESCRIPTION\n{NAME} reads each \\fIFILE\\fR and prints the lines containing \\fIQUERY\\fR.\nWhen more than one file is searched, each line is prefixed with its file name.\nResults are written to stdout and errors to stderr.\n.SH ARGUMENTS\n",
+        NAME.to_uppercase(),
+        env!("CARGO_PKG_VERSION"),
+        args.join(" ")
+    );
+    for (name, help) in ARGS {
+        s.push_str(&format!(".TP\n\\fI{name}\\fR\n{}\n", roff(help)));
+    }
+    s.push_str(".SH OPTIONS\n");
+    for opt in visible() {
+        let mut forms = Vec::new();
+        if let Some(c) = opt.short {
+            forms.push(format!("\\fB\\-{c}\\fR"));
+        }
+        forms.push(format!("\\fB\\-\\-{}\\fR", roff(opt.long)));
+        let value = opt
+            .value
+            .map(|v| format!(" \\fI{v}\\fR"))
+            .unwrap_or_default();
+        s.push_str(&format!(
+            ".TP\n{}{value}\n{}\n",
+            forms.join(", "),
+            roff(opt.help)
+        ));
+    }
+    s.push_str(
+        ".SH EXIT STATUS\n0 on success, 1 if the arguments are invalid or an error occurred.\n",
+    );
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(cmd: &str) -> Result<Command, &'static str> {
+        Command::parse(cmd.split(' ').map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn case_parse() {
+        assert_eq!(Ok(Command::Help), parse("minigrep -h"));
+        assert_eq!(Ok(Command::Help), parse("minigrep say --help poem.txt"));
+        assert_eq!(
+            Ok(Command::Generate(Target::Zsh)),
+            parse("minigrep --generate zsh")
+        );
+        assert_eq!(
+            Ok(Command::Generate(Target::Man)),
+            parse("minigrep --generate=man")
+        );
+
+        assert_eq!(Err(ERROR_MISS_VALUE), parse("minigrep --generate"));
+        assert_eq!(Err(ERROR_INVALID_VALUE), parse("minigrep --generate ksh"));
+        assert_eq!(Err(ERROR_UNEXPECTED_VALUE), parse("minigrep --help=yes"));
+        assert_eq!(
+            Err(ERROR_UNKNOWN_OPTION),
+            parse("minigrep --nope say poem.txt")
+        );
+        assert_eq!(Err(ERROR_UNKNOWN_OPTION), parse("minigrep -x say poem.txt"));
+
+        assert_eq!(
+            Ok(Command::Search(Config {
+                query: "-say".to_string(),
+                file_paths: vec!["poem.txt".to_string()],
+                ..Default::default()
+            })),
+            parse("minigrep -- -say poem.txt")
+        );
+        assert_eq!(
+            Ok(Command::Search(Config {
+                query: "say".to_string(),
+                file_paths: vec!["-".to_string(), "poem.txt".to_string()],
+                ..Default::default()
+            })),
+            parse("minigrep say - poem.txt")
+        );
+
+        assert_eq!(
+            Ok(Command::Search(Config {
+                query: "say".to_string(),
+                file_paths: vec!["poem.txt".to_string()],
+                case: Case::Smart,
+                normalization: Some(Normalization::Nfkc),
+                ..Default::default()
+            })),
+            parse("minigrep -iS say --normalize=nfkc poem.txt")
+        );
+        assert_eq!(
+            Ok(Command::Search(Config {
+                query: "say".to_string(),
+                file_paths: vec!["a.txt".to_string(), "dir".to_string()],
+                sort: Some(Sort::Modified),
+                stats: true,
+                ..Default::default()
+            })),
+            parse("minigrep say a.txt --stats --sort modified dir")
+        );
+        assert_eq!(
+            Ok(Command::Search(Config {
+                query: "say".to_string(),
+                file_paths: vec!["dir".to_string()],
+                files_with_matches: true,
+                null: true,
+                null_data: true,
+                ..Default::default()
+            })),
+            parse("minigrep -l0 --null-data say dir")
+        );
+        assert_eq!(
+            Err(ERROR_INVALID_VALUE),
+            parse("minigrep --normalize nfd say poem.txt")
+        );
+    }
+
+    #[test]
+    fn case_generate() {
+        let docs: Vec<_> = [Target::Bash, Target::Zsh, Target::Fish, Target::Man]
+            .into_iter()
+            .map(generate)
+            .collect();
+
+        // every visible option is documented everywhere, hidden
+        // ones nowhere.
+        for opt in OPTIONS {
+            let long = format!("--{}", opt.long);
+            for doc in &docs[..3] {
+                assert_eq!(
+                    !opt.hidden,
+                    doc.contains(&long) || doc.contains(&format!("-l {}", opt.long))
+                );
+            }
+            assert_eq!(!opt.hidden, docs[3].contains(&roff(&long)));
+            assert_eq!(!opt.hidden, help().contains(&long));
+        }
+        assert!(docs[3].starts_with(".TH MINIGREP 1"));
+    }
+}
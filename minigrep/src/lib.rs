@@ -12,12 +12,19 @@
 //!
 //! ```bash
 //! minigrep searchstring example-filename.txt
+//! minigrep --sort path --stats searchstring a.txt some-directory
 //! ```
 //!
 //! Note:
 //!
 //! * It writes result to stdout, and writes error info to stderr
 //! * It prints helper information if its argment is incorrect
+//! * It prefixes lines with the file name when searching many files
+//! * It skips a path it cannot read, and then exits with status 2
+//! * `minigrep -l0 query dir | xargs -0 ...` is safe for any file name
+//! * `minigrep --help` prints all options
+//! * `minigrep --generate bash|zsh|fish|man` prints a shell completion
+//!   script or the man page, see [`cli`]
 //!
 //! ## As a library
 //!
@@ -27,20 +34,81 @@
 #![deny(missing_docs)]
 
 use std::error::Error;
+use std::fmt;
 use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use unicode_normalization::UnicodeNormalization;
+
+pub mod cli;
+mod walk;
+
+use cli::Command;
 
 /// holds arguments for the grep functionality, as well as
 /// options for its behavior.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Default)]
 pub struct Config {
     /// the string to search
     pub query: String,
-    /// the file to search from
-    pub file_path: String,
+    /// the files to search from, directories are searched recursively,
+    /// [`STDIN`] for stdin
+    pub file_paths: Vec<String>,
+    /// how letter case is compared
+    pub case: Case,
+    /// the Unicode form both query and text are normalized to
+    /// before matching, if any
+    pub normalization: Option<Normalization>,
+    /// the order in which files are searched, if any
+    pub sort: Option<Sort>,
+    /// whether to print statistics to stderr when done
+    pub stats: bool,
+    /// whether to print only the names of files with matches
+    pub files_with_matches: bool,
+    /// whether to terminate file names with NUL instead of `:` or
+    /// a newline
+    pub null: bool,
+    /// whether input and output records are separated by NUL
+    /// instead of newlines
+    pub null_data: bool,
 }
 
-const ERROR_MISS_QUERY: &str = "miss query string in the arguments";
-const ERROR_MISS_FILEPATH: &str = "miss filepath in the arguments";
+/// how letter case is compared while searching.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Case {
+    /// `a` only matches `a`
+    #[default]
+    Sensitive,
+    /// `a` matches both `a` and `A`
+    Insensitive,
+    /// insensitive unless the query contains an uppercase letter
+    Smart,
+}
+
+/// a Unicode normalization form.
+///
+/// Normalizing both the query and the text lets a composed `é`
+/// (U+00E9) match a decomposed `e` followed by U+0301.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Normalization {
+    /// canonical composition
+    Nfc,
+    /// compatibility composition, which also folds e.g. `ﬁ` to `fi`
+    Nfkc,
+}
+
+/// the order in which files are searched, so that the output
+/// is the same from run to run.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Sort {
+    /// by path, in byte order
+    Path,
+    /// by modification time, oldest first
+    Modified,
+}
+
+const ERROR_NO_SEARCH: &str = "no search requested in the arguments";
 
 impl Config {
     /// Constructs a config for the grep functionality to run.
@@ -57,70 +125,270 @@ impl Config {
     /// assert_eq!(
     /// Config {
     ///   query: "searchstring".to_string(),
-    ///   file_path: "example.txt".to_string(),
+    ///   file_paths: vec!["example.txt".to_string()],
+    ///   ..Default::default()
     /// },
     /// config);
     ///```
-    pub fn build(mut args: impl Iterator<Item = String>) -> Result<Config, &'static str> {
-        args.next();
+    ///
+    /// It fails if the arguments ask for something other than a
+    /// search, e.g. `--help`. Use [`Command::parse`] to handle those.
+    pub fn build(args: impl Iterator<Item = String>) -> Result<Config, &'static str> {
+        match Command::parse(args)? {
+            Command::Search(config) => Ok(config),
+            _ => Err(ERROR_NO_SEARCH),
+        }
+    }
+}
 
-        let query = match args.next() {
-            Some(arg) => arg,
-            None => return Err(ERROR_MISS_QUERY),
-        };
+/// the file path which stands for stdin, as in `grep`.
+pub const STDIN: &str = "-";
 
-        let file_path = match args.next() {
-            Some(arg) => arg,
-            None => return Err(ERROR_MISS_FILEPATH),
-        };
+/// how stdin is named in the output.
+const STDIN_NAME: &str = "(standard input)";
+
+/// the error of [`run`] when some paths could not be searched.
+///
+/// Each of them was already reported to stderr as
+/// `minigrep: <path>: <error>`, and the others were searched.
+#[derive(Debug, PartialEq)]
+pub struct PathErrors {
+    /// how many paths could not be searched
+    pub count: usize,
+}
 
-        Ok(Config { query, file_path })
+impl fmt::Display for PathErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} path(s) could not be searched", self.count)
     }
 }
 
+impl Error for PathErrors {}
+
 /// takes a configuration and runs the grep functionaly.
+///
+/// A file or directory which cannot be read is reported to stderr
+/// and skipped; once the others are searched, it fails with
+/// [`PathErrors`].
 pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let text = fs::read_to_string(config.file_path)?;
+    let start = Instant::now();
+    let matcher = Matcher::new(&config);
+    let (files, failed) = walk::files(&config.file_paths, config.sort);
+    let mut errors = failed.len();
+    for (path, e) in failed {
+        report_error(&path, &e);
+    }
+    // by the paths asked for, not those found: a file left out
+    // must not change how the others are printed
+    let with_file_name =
+        config.file_paths.len() > 1 || config.file_paths.iter().any(|p| Path::new(p).is_dir());
+    let mut stats = Vec::with_capacity(files.len());
+    let mut out = io::stdout().lock();
+
+    let (name_end, list_end) = match config.null {
+        true => (b"\0", b"\0"),
+        false => (b":", b"\n"),
+    };
+    let record_end = if config.null_data { b"\0" } else { b"\n" };
+
+    for path in files {
+        let read = if path.as_os_str() == STDIN {
+            let mut bytes = Vec::new();
+            io::stdin().lock().read_to_end(&mut bytes).map(|_| bytes)
+        } else {
+            fs::read(&path)
+        };
+        let path = match path.as_os_str() == STDIN {
+            true => PathBuf::from(STDIN_NAME),
+            false => path,
+        };
+        let bytes = match read {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                report_error(&path, &e);
+                errors += 1;
+                continue;
+            }
+        };
+        let text;
+        let matches: Vec<&[u8]> = if config.null_data {
+            // matched as text, but written back byte for byte
+            null_records(&bytes)
+                .filter(|record| matcher.is_match(&String::from_utf8_lossy(record)))
+                .collect()
+        } else {
+            text = String::from_utf8_lossy(&bytes);
+            let lines = search(&matcher, text.lines());
+            lines.into_iter().map(str::as_bytes).collect()
+        };
 
-    let matches = search(&config.query, &text);
+        if config.files_with_matches {
+            if !matches.is_empty() {
+                write_path(&mut out, &path)?;
+                out.write_all(list_end)?;
+            }
+        } else {
+            for line in &matches {
+                if with_file_name {
+                    write_path(&mut out, &path)?;
+                    out.write_all(name_end)?;
+                }
+                out.write_all(line)?;
+                out.write_all(record_end)?;
+            }
+        }
+
+        stats.push(FileStats {
+            matches: matches.len(),
+            bytes: bytes.len(),
+            path,
+        });
+    }
 
-    for line in matches {
-        println!("{line}");
+    out.flush()?;
+    if config.stats {
+        eprint!("{}", report(&stats, start.elapsed()));
     }
 
-    Ok(())
+    match errors {
+        0 => Ok(()),
+        count => Err(Box::new(PathErrors { count })),
+    }
+}
+
+/// reports a path which cannot be searched, like `grep` does.
+fn report_error(path: &Path, e: &io::Error) {
+    eprintln!("minigrep: {}: {e}", path.display());
+}
+
+/// splits `bytes` into the records ended by NUL, the last one maybe
+/// not, like `str::split_terminator`.
+fn null_records(bytes: &[u8]) -> impl Iterator<Item = &[u8]> {
+    bytes
+        .split_inclusive(|&b| b == 0)
+        .map(|record| record.strip_suffix(b"\0").unwrap_or(record))
 }
 
-fn search<'a>(query: &str, text: &'a str) -> Vec<&'a str> {
-    text.lines().filter(|line| line.contains(query)).collect()
+/// writes the path as is, so that names which are not valid
+/// UTF-8 still reach e.g. `xargs -0` intact.
+#[cfg(unix)]
+fn write_path(out: &mut impl Write, path: &Path) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    out.write_all(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_path(out: &mut impl Write, path: &Path) -> io::Result<()> {
+    write!(out, "{}", path.display())
+}
+
+/// what was found in one file, for `--stats`.
+struct FileStats {
+    path: PathBuf,
+    bytes: usize,
+    matches: usize,
+}
+
+fn report(stats: &[FileStats], elapsed: Duration) -> String {
+    let mut s = String::new();
+    for file in stats {
+        s.push_str(&format!(
+            "{}: {} matches in {} bytes\n",
+            file.path.display(),
+            file.matches,
+            file.bytes
+        ));
+    }
+    s.push_str(&format!("files searched: {}\n", stats.len()));
+    s.push_str(&format!(
+        "bytes scanned: {}\n",
+        stats.iter().map(|f| f.bytes).sum::<usize>()
+    ));
+    s.push_str(&format!(
+        "matches: {}\n",
+        stats.iter().map(|f| f.matches).sum::<usize>()
+    ));
+    s.push_str(&format!("elapsed: {elapsed:?}\n"));
+    s
+}
+
+/// compares lines against a query prepared once according to
+/// the case and normalization options.
+struct Matcher {
+    query: String,
+    ignore_case: bool,
+    normalization: Option<Normalization>,
+}
+
+impl Matcher {
+    fn new(config: &Config) -> Matcher {
+        let ignore_case = match config.case {
+            Case::Sensitive => false,
+            Case::Insensitive => true,
+            Case::Smart => !config.query.chars().any(char::is_uppercase),
+        };
+
+        let mut matcher = Matcher {
+            query: String::new(),
+            ignore_case,
+            normalization: config.normalization,
+        };
+        matcher.query = matcher.prepare(&config.query);
+        matcher
+    }
+
+    /// normalizes first, as lowercasing a composed letter and its
+    /// decomposed form must give the same result.
+    fn prepare(&self, s: &str) -> String {
+        let s = match self.normalization {
+            Some(Normalization::Nfc) => s.nfc().collect(),
+            Some(Normalization::Nfkc) => s.nfkc().collect(),
+            None => s.to_string(),
+        };
+        if self.ignore_case {
+            s.to_lowercase()
+        } else {
+            s
+        }
+    }
+
+    fn is_match(&self, line: &str) -> bool {
+        if !self.ignore_case && self.normalization.is_none() {
+            return line.contains(&self.query);
+        }
+        self.prepare(line).contains(&self.query)
+    }
+}
+
+fn search<'a>(matcher: &Matcher, records: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    records.filter(|record| matcher.is_match(record)).collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use cli::{ERROR_MISS_FILEPATH, ERROR_MISS_QUERY};
 
     #[test]
     fn case_run() {
         // case 1: file not exist
         let x = run(Config {
             query: "say".to_string(),
-            file_path: "no-exist-file.txt".to_string(),
+            file_paths: vec!["no-exist-file.txt".to_string(), "poem.txt".to_string()],
+            ..Default::default()
         });
-        assert!(x.is_err());
+        let err = x.unwrap_err().downcast::<PathErrors>().unwrap();
+        assert_eq!(PathErrors { count: 1 }, *err);
 
         // case 2: case sensitive searching
         let x = run(Config {
             query: "say".to_string(),
-            file_path: "poem.txt".to_string(),
+            file_paths: vec!["poem.txt".to_string()],
+            ..Default::default()
         });
         assert_eq!((), x.expect("REASON"));
     }
 
-    #[allow(dead_code)]
-    fn case_run_output() {
-        // TODO
-    }
-
     #[test]
     fn case_build() {
         case_config_build_with_invalid_args();
@@ -133,17 +401,17 @@ mod tests {
             Config::build(vec![].into_iter()).unwrap_err()
         );
 
-        let mut v = Vec::new();
-        v.push(String::from("minigrep"));
+        let v = vec![String::from("minigrep")];
         assert_eq!(ERROR_MISS_QUERY, Config::build(v.into_iter()).unwrap_err());
 
-        let mut v = Vec::new();
-        v.push(String::from("minigrep"));
-        v.push(String::from("say"));
+        let v = vec![String::from("minigrep"), String::from("say")];
         assert_eq!(
             ERROR_MISS_FILEPATH,
             Config::build(v.into_iter()).unwrap_err()
         );
+
+        let v = vec![String::from("minigrep"), String::from("--help")];
+        assert_eq!(ERROR_NO_SEARCH, Config::build(v.into_iter()).unwrap_err());
     }
 
     fn case_config_build() {
@@ -154,12 +422,39 @@ mod tests {
         assert_eq!(
             Config {
                 query: "searchstring".to_string(),
-                file_path: "example.txt".to_string(),
+                file_paths: vec!["example.txt".to_string()],
+                ..Default::default()
             },
             Config::build(cmd).unwrap()
         );
     }
 
+    #[test]
+    fn case_report() {
+        let stats = vec![
+            FileStats {
+                path: PathBuf::from("a.txt"),
+                bytes: 10,
+                matches: 2,
+            },
+            FileStats {
+                path: PathBuf::from("b.txt"),
+                bytes: 5,
+                matches: 0,
+            },
+        ];
+        assert_eq!(
+            "a.txt: 2 matches in 10 bytes
+b.txt: 0 matches in 5 bytes
+files searched: 2
+bytes scanned: 15
+matches: 2
+elapsed: 3ms
+",
+            report(&stats, Duration::from_millis(3))
+        );
+    }
+
     #[test]
     fn case_search() {
         let s = "hello,
@@ -168,6 +463,84 @@ say sorry,
 say Yes,
 say yes,
 say thank you,";
-        assert_eq!(vec!["say yes,"], search("yes", s));
+        let matcher = Matcher::new(&Config {
+            query: "yes".to_string(),
+            ..Default::default()
+        });
+        assert_eq!(vec!["say yes,"], search(&matcher, s.lines()));
+    }
+
+    #[test]
+    fn case_search_case() {
+        let s = "say Yes,
+say yes,
+SAY NO,";
+        let matcher = |query: &str, case| {
+            Matcher::new(&Config {
+                query: query.to_string(),
+                case,
+                ..Default::default()
+            })
+        };
+
+        let m = matcher("yes", Case::Insensitive);
+        assert_eq!(vec!["say Yes,", "say yes,"], search(&m, s.lines()));
+        let m = matcher("Yes", Case::Insensitive);
+        assert_eq!(vec!["say Yes,", "say yes,"], search(&m, s.lines()));
+
+        // smart case: lowercase query ignores case, otherwise not
+        let m = matcher("yes", Case::Smart);
+        assert_eq!(vec!["say Yes,", "say yes,"], search(&m, s.lines()));
+        let m = matcher("Yes", Case::Smart);
+        assert_eq!(vec!["say Yes,"], search(&m, s.lines()));
+        let m = matcher("say", Case::Smart);
+        assert_eq!(
+            vec!["say Yes,", "say yes,", "SAY NO,"],
+            search(&m, s.lines())
+        );
+    }
+
+    #[test]
+    fn case_search_normalization() {
+        // composed U+00E9 and decomposed e + U+0301
+        let s = "caf\u{e9} au lait
+cafe\u{301} noir
+tea";
+        let matcher = |query: &str, normalization| {
+            Matcher::new(&Config {
+                query: query.to_string(),
+                normalization,
+                ..Default::default()
+            })
+        };
+
+        assert_eq!(
+            vec!["caf\u{e9} au lait"],
+            search(&matcher("caf\u{e9}", None), s.lines())
+        );
+        let both = vec!["caf\u{e9} au lait", "cafe\u{301} noir"];
+        let m = matcher("caf\u{e9}", Some(Normalization::Nfc));
+        assert_eq!(both, search(&m, s.lines()));
+        let m = matcher("cafe\u{301}", Some(Normalization::Nfc));
+        assert_eq!(both, search(&m, s.lines()));
+
+        // compatibility forms only match under NFKC
+        let s = "\u{fb01}le";
+        let m = matcher("file", Some(Normalization::Nfc));
+        assert!(search(&m, s.lines()).is_empty());
+        let m = matcher("file", Some(Normalization::Nfkc));
+        assert_eq!(vec![s], search(&m, s.lines()));
+
+        // normalization combines with ignoring case
+        let m = Matcher::new(&Config {
+            query: "CAF\u{c9}".to_string(),
+            case: Case::Insensitive,
+            normalization: Some(Normalization::Nfc),
+            ..Default::default()
+        });
+        assert_eq!(
+            vec!["cafe\u{301} noir"],
+            search(&m, "cafe\u{301} noir".lines())
+        );
     }
 }
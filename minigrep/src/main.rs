@@ -1,15 +1,32 @@
-use minigrep;
-use minigrep::Config;
+use minigrep::cli::{self, Command};
+use minigrep::PathErrors;
 use std::env;
 use std::process;
 
 fn main() {
-    let config = Config::build(env::args()).unwrap_or_else(|err| {
+    let command = Command::parse(env::args()).unwrap_or_else(|err| {
         eprintln!("Problem parsing arguments: {err}");
+        eprintln!("{}", cli::usage());
         process::exit(1);
     });
 
+    let config = match command {
+        Command::Search(config) => config,
+        Command::Help => {
+            print!("{}", cli::help());
+            return;
+        }
+        Command::Generate(target) => {
+            print!("{}", cli::generate(target));
+            return;
+        }
+    };
+
     if let Err(e) = minigrep::run(config) {
+        // each path was reported as it failed
+        if e.is::<PathErrors>() {
+            process::exit(2);
+        }
         eprintln!("Application error: {}", e);
         process::exit(1);
     }
@@ -0,0 +1,139 @@
+//! `walk` expands the paths given on the command line into the
+//! files to search.
+
+use crate::{Sort, STDIN};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// a path which could not be listed, and why.
+pub(crate) type Failed = (PathBuf, io::Error);
+
+/// lists the files under `paths`, descending into directories.
+///
+/// Like `grep -r`, symbolic links given on the command line are
+/// followed while those found inside directories are skipped.
+/// Without `sort`, files come in command line order and then in
+/// the order the file system lists them. [`STDIN`] is kept as is.
+///
+/// A path which cannot be listed is left out and returned with its
+/// error, so that one unreadable directory does not stop the rest.
+pub(crate) fn files(paths: &[String], sort: Option<Sort>) -> (Vec<PathBuf>, Vec<Failed>) {
+    let mut files = Vec::new();
+    let mut failed = Vec::new();
+    for path in paths {
+        let path = Path::new(path);
+        if path.as_os_str() == STDIN {
+            files.push(path.to_path_buf());
+            continue;
+        }
+        match fs::metadata(path) {
+            Ok(metadata) if metadata.is_dir() => walk_dir(path, &mut files, &mut failed),
+            Ok(_) => files.push(path.to_path_buf()),
+            Err(e) => failed.push((path.to_path_buf(), e)),
+        }
+    }
+
+    match sort {
+        Some(Sort::Path) => files.sort(),
+        Some(Sort::Modified) => {
+            let mut keyed = Vec::with_capacity(files.len());
+            for file in files {
+                // stdin has no modification time; it comes first
+                if file.as_os_str() == STDIN {
+                    keyed.push((None, file));
+                    continue;
+                }
+                match fs::metadata(&file).and_then(|metadata| metadata.modified()) {
+                    Ok(modified) => keyed.push((Some(modified), file)),
+                    Err(e) => failed.push((file, e)),
+                }
+            }
+            keyed.sort();
+            files = keyed.into_iter().map(|(_, file)| file).collect();
+        }
+        None => (),
+    }
+
+    (files, failed)
+}
+
+fn walk_dir(dir: &Path, files: &mut Vec<PathBuf>, failed: &mut Vec<Failed>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => return failed.push((dir.to_path_buf(), e)),
+    };
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                failed.push((dir.to_path_buf(), e));
+                continue;
+            }
+        };
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => walk_dir(&entry.path(), files, failed),
+            Ok(file_type) if file_type.is_file() => files.push(entry.path()),
+            Ok(_) => (),
+            Err(e) => failed.push((entry.path(), e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn case_files() {
+        let root = std::env::temp_dir().join(format!("minigrep-walk-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("b/c")).unwrap();
+
+        // created in one order, modified in another
+        let now = SystemTime::now();
+        for (name, age) in [("b/c/z.txt", 3), ("a.txt", 1), ("b/y.txt", 2)] {
+            let file = File::create(root.join(name)).unwrap();
+            file.set_modified(now - Duration::from_secs(age * 60))
+                .unwrap();
+        }
+
+        let arg = vec![root.to_string_lossy().to_string()];
+        let names = |files: Vec<PathBuf>| -> Vec<String> {
+            files
+                .iter()
+                .map(|f| {
+                    f.strip_prefix(&root)
+                        .unwrap()
+                        .to_string_lossy()
+                        .replace('\\', "/")
+                })
+                .collect()
+        };
+
+        let mut unsorted = names(files(&arg, None).0);
+        unsorted.sort();
+        assert_eq!(vec!["a.txt", "b/c/z.txt", "b/y.txt"], unsorted);
+        assert_eq!(
+            vec!["a.txt", "b/c/z.txt", "b/y.txt"],
+            names(files(&arg, Some(Sort::Path)).0)
+        );
+        assert_eq!(
+            vec!["b/c/z.txt", "b/y.txt", "a.txt"],
+            names(files(&arg, Some(Sort::Modified)).0)
+        );
+
+        // a missing path is reported, the others still listed
+        let missing = root.join("missing");
+        let args = vec![missing.to_string_lossy().to_string(), arg[0].clone()];
+        let (found, failed) = files(&args, Some(Sort::Path));
+        assert_eq!(vec!["a.txt", "b/c/z.txt", "b/y.txt"], names(found));
+        assert_eq!(1, failed.len());
+        assert_eq!(missing, failed[0].0);
+        assert_eq!(io::ErrorKind::NotFound, failed[0].1.kind());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
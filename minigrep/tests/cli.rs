@@ -0,0 +1,118 @@
+//! End-to-end tests running the `minigrep` binary.
+//!
+//! Every directory under `tests/fixtures` is one case. The binary
+//! runs inside that directory, so a case keeps its input files next
+//! to the expectations:
+//!
+//! * `args` - the arguments, one per line
+//! * `stdin` - the input piped to the binary, empty if missing
+//! * `stdout` - the exact expected stdout, byte for byte, or
+//! * `stdout.contains` - lines which must all appear in stdout
+//! * `stderr` - the exact expected stderr, empty if missing, or
+//! * `stderr.contains` - lines which must all appear in stderr
+//! * `status` - the expected exit code, 0 if missing
+//!
+//! Inputs used by several cases, like `poem.txt`, sit in
+//! `tests/fixtures` itself and are named `../poem.txt`.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+fn read(path: &Path) -> String {
+    fs::read_to_string(path).unwrap_or_else(|e| panic!("{}: {e}", path.display()))
+}
+
+/// reads the file `name` of a case, `None` if the case has none.
+fn read_optional(dir: &Path, name: &str) -> Option<String> {
+    let path = dir.join(name);
+    path.exists().then(|| read(&path))
+}
+
+fn fixtures() -> Vec<PathBuf> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut dirs: Vec<_> = fs::read_dir(root)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_dir())
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+/// runs one case, returning what differs from the expectations.
+fn run_case(dir: &Path) -> Vec<String> {
+    let args = read(&dir.join("args"));
+    let mut child = Command::new(env!("CARGO_BIN_EXE_minigrep"))
+        .args(args.lines())
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // dropped once written, so that the binary sees the end
+    let mut stdin = child.stdin.take().unwrap();
+    let input = read_optional(dir, "stdin").unwrap_or_default();
+    stdin.write_all(input.as_bytes()).unwrap();
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let mut failures = Vec::new();
+
+    let path = dir.join("stdout");
+    if path.exists() {
+        let expected = fs::read(&path).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+        if output.stdout != expected {
+            let expected = String::from_utf8_lossy(&expected);
+            failures.push(format!("stdout:\n{stdout}\nexpected:\n{expected}"));
+        }
+    }
+    if let Some(expected) = read_optional(dir, "stdout.contains") {
+        for line in expected.lines().filter(|line| !stdout.contains(line)) {
+            failures.push(format!("stdout misses {line:?}:\n{stdout}"));
+        }
+    }
+
+    if let Some(expected) = read_optional(dir, "stderr.contains") {
+        for line in expected.lines().filter(|line| !stderr.contains(line)) {
+            failures.push(format!("stderr misses {line:?}:\n{stderr}"));
+        }
+    } else {
+        let expected = read_optional(dir, "stderr").unwrap_or_default();
+        if stderr != expected {
+            failures.push(format!("stderr:\n{stderr}\nexpected:\n{expected}"));
+        }
+    }
+
+    let expected = read_optional(dir, "status").map_or(0, |s| s.trim().parse().unwrap());
+    if output.status.code() != Some(expected) {
+        failures.push(format!(
+            "status {:?}, expected {expected}",
+            output.status.code()
+        ));
+    }
+
+    failures
+}
+
+#[test]
+fn case_fixtures() {
+    let dirs = fixtures();
+    assert!(!dirs.is_empty());
+
+    let mut failures = Vec::new();
+    for dir in &dirs {
+        for failure in run_case(dir) {
+            failures.push(format!(
+                "{}: {failure}",
+                dir.file_name().unwrap().to_string_lossy()
+            ));
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n\n"));
+}
@@ -0,0 +1,177 @@
+//! Compares the throughput of `ThreadPool` with the previous
+//! design, where every worker waited on one
+//! `Arc<Mutex<mpsc::Receiver<Job>>>`.
+//!
+//! Run it with `cargo bench`. Each workload runs many short jobs
+//! and reports jobs per second, the best of a few rounds.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use threadpool::ThreadPool;
+
+const WORKERS: usize = 4;
+const JOBS: usize = 200_000;
+const ROUNDS: usize = 5;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// the previous design, kept here as the baseline.
+struct MutexPool {
+    sender: Option<mpsc::Sender<Job>>,
+    threads: Vec<thread::JoinHandle<()>>,
+}
+
+impl MutexPool {
+    fn new(size: usize) -> MutexPool {
+        let (tx, rx) = mpsc::channel::<Job>();
+        let rx = Arc::new(Mutex::new(rx));
+        let threads = (0..size)
+            .map(|_| {
+                let rx = Arc::clone(&rx);
+                thread::spawn(move || loop {
+                    let job = rx.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => return,
+                    }
+                })
+            })
+            .collect();
+        MutexPool {
+            sender: Some(tx),
+            threads,
+        }
+    }
+
+    fn sender(&self) -> mpsc::Sender<Job> {
+        self.sender.clone().unwrap()
+    }
+}
+
+impl Drop for MutexPool {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        for thread in self.threads.drain(..) {
+            thread.join().unwrap();
+        }
+    }
+}
+
+fn wait_for(done: &AtomicUsize, n: usize) {
+    while done.load(Ordering::Acquire) < n {
+        thread::yield_now();
+    }
+}
+
+/// `JOBS` tiny jobs queued from the main thread.
+fn flat_mutex() -> Duration {
+    let pool = MutexPool::new(WORKERS);
+    let tx = pool.sender();
+    let done = Arc::new(AtomicUsize::new(0));
+    let start = Instant::now();
+    for _ in 0..JOBS {
+        let done = Arc::clone(&done);
+        tx.send(Box::new(move || {
+            done.fetch_add(1, Ordering::Release);
+        }))
+        .unwrap();
+    }
+    wait_for(&done, JOBS);
+    start.elapsed()
+}
+
+fn flat_stealing() -> Duration {
+    let pool = ThreadPool::new(WORKERS);
+    let done = Arc::new(AtomicUsize::new(0));
+    let start = Instant::now();
+    for _ in 0..JOBS {
+        let done = Arc::clone(&done);
+        pool.execute(move || {
+            done.fetch_add(1, Ordering::Release);
+        });
+    }
+    wait_for(&done, JOBS);
+    start.elapsed()
+}
+
+/// a few jobs, each queueing `JOBS / OUTER` tiny jobs itself.
+const OUTER: usize = 100;
+
+fn nested_mutex() -> Duration {
+    let pool = MutexPool::new(WORKERS);
+    let tx = pool.sender();
+    let done = Arc::new(AtomicUsize::new(0));
+    let start = Instant::now();
+    for _ in 0..OUTER {
+        let inner_tx = tx.clone();
+        let done = Arc::clone(&done);
+        tx.send(Box::new(move || {
+            for _ in 0..JOBS / OUTER {
+                let done = Arc::clone(&done);
+                inner_tx
+                    .send(Box::new(move || {
+                        done.fetch_add(1, Ordering::Release);
+                    }))
+                    .unwrap();
+            }
+        }))
+        .unwrap();
+    }
+    wait_for(&done, JOBS);
+    start.elapsed()
+}
+
+fn nested_stealing() -> Duration {
+    let pool = Arc::new(ThreadPool::new(WORKERS));
+    let done = Arc::new(AtomicUsize::new(0));
+    let start = Instant::now();
+    for _ in 0..OUTER {
+        let inner = Arc::clone(&pool);
+        let done = Arc::clone(&done);
+        pool.execute(move || {
+            for _ in 0..JOBS / OUTER {
+                let done = Arc::clone(&done);
+                inner.execute(move || {
+                    done.fetch_add(1, Ordering::Release);
+                });
+            }
+        });
+    }
+    wait_for(&done, JOBS);
+    let elapsed = start.elapsed();
+    // the jobs hold clones of the pool; let the last one go here
+    // rather than on a worker, which cannot join itself.
+    while Arc::strong_count(&pool) > 1 {
+        thread::yield_now();
+    }
+    elapsed
+}
+
+fn best(f: fn() -> Duration) -> f64 {
+    let best = (0..ROUNDS).map(|_| f()).min().unwrap();
+    JOBS as f64 / best.as_secs_f64()
+}
+
+fn main() {
+    println!("{WORKERS} workers, {JOBS} jobs, best of {ROUNDS} rounds");
+    println!(
+        "{:<10} {:>18} {:>18}",
+        "workload", "Mutex<Receiver>", "work stealing"
+    );
+    for (name, mutex, stealing) in [
+        (
+            "flat",
+            flat_mutex as fn() -> Duration,
+            flat_stealing as fn() -> Duration,
+        ),
+        ("nested", nested_mutex, nested_stealing),
+    ] {
+        println!(
+            "{name:<10} {:>12.0} jobs/s {:>12.0} jobs/s",
+            best(mutex),
+            best(stealing)
+        );
+    }
+}
@@ -0,0 +1,69 @@
+//! `affinity` pins worker threads to CPU cores.
+//!
+//! Only Linux is supported; elsewhere pinning does nothing, so a
+//! pool configured for a benchmark machine still runs anywhere.
+
+use std::io;
+
+/// pins the calling thread to `core`.
+#[cfg(target_os = "linux")]
+pub(crate) fn pin(core: usize) -> io::Result<()> {
+    if core >= libc::CPU_SETSIZE as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("there is no CPU core {core}"),
+        ));
+    }
+
+    // SAFETY: `cpu_set_t` is a plain bit set, valid when zeroed, and
+    // `core` was checked to fit in it.
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn pin(_core: usize) -> io::Result<()> {
+    Ok(())
+}
+
+/// the cores the calling thread may run on, which need not start
+/// at 0 under `taskset` or a cgroup cpuset.
+#[cfg(all(test, target_os = "linux"))]
+pub(crate) fn cores() -> Vec<usize> {
+    // SAFETY: as in `pin`
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        let size = std::mem::size_of::<libc::cpu_set_t>();
+        assert_eq!(0, libc::sched_getaffinity(0, size, &mut set));
+        (0..libc::CPU_SETSIZE as usize)
+            .filter(|&core| libc::CPU_ISSET(core, &set))
+            .collect()
+    }
+}
+
+#[cfg(all(test, not(target_os = "linux")))]
+pub(crate) fn cores() -> Vec<usize> {
+    vec![0]
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn case_pin() {
+        let core = cores()[0];
+        let pinned = thread::spawn(move || pin(core).map(|()| cores()));
+        assert_eq!(vec![core], pinned.join().unwrap().unwrap());
+
+        let err = pin(usize::MAX).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+    }
+}
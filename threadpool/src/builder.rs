@@ -0,0 +1,345 @@
+//! `builder` configures a [`ThreadPool`] before its workers start.
+
+use crate::queue::Queue;
+use crate::timer::Timer;
+use crate::worker::Workers;
+use crate::{lock, Counters, Listener, PanicHandler, Shared, ThreadPool};
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::num::NonZeroUsize;
+use std::sync::atomic::AtomicUsize;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// configures and creates a [`ThreadPool`].
+///
+/// ```rust
+/// use threadpool::ThreadPool;
+///
+/// let pool = ThreadPool::builder()
+///     .size(4)
+///     .thread_name("server")
+///     .stack_size(256 * 1024)
+///     .build()
+///     .unwrap();
+///
+/// let name = pool.spawn(|| std::thread::current().name().map(String::from));
+/// assert!(name.join().unwrap().unwrap().starts_with("server-"));
+/// ```
+#[derive(Default)]
+pub struct Builder {
+    size: Option<usize>,
+    max_size: Option<usize>,
+    keep_alive: Option<Duration>,
+    queue_capacity: Option<usize>,
+    thread: ThreadConfig,
+    panic_handler: Option<Arc<PanicHandler>>,
+    listener: Option<Arc<dyn Listener>>,
+}
+
+/// how worker threads are spawned, kept to spawn replacements.
+#[derive(Default, Clone)]
+pub(crate) struct ThreadConfig {
+    name: Option<String>,
+    stack_size: Option<usize>,
+    cores: Vec<usize>,
+}
+
+/// how long an extra worker stays idle before it retires, unless
+/// set with [`Builder::keep_alive`].
+const KEEP_ALIVE: Duration = Duration::from_secs(60);
+
+impl ThreadConfig {
+    pub(crate) fn builder(&self, id: usize) -> std::thread::Builder {
+        let mut builder = std::thread::Builder::new();
+        if let Some(name) = &self.name {
+            builder = builder.name(format!("{name}-{id}"));
+        }
+        if let Some(size) = self.stack_size {
+            builder = builder.stack_size(size);
+        }
+        builder
+    }
+
+    /// the core worker `id` is pinned to, if any.
+    pub(crate) fn core(&self, id: usize) -> Option<usize> {
+        if self.cores.is_empty() {
+            return None;
+        }
+        Some(self.cores[id % self.cores.len()])
+    }
+
+    pub(crate) fn timer_builder(&self) -> std::thread::Builder {
+        let mut builder = std::thread::Builder::new();
+        if let Some(name) = &self.name {
+            builder = builder.name(format!("{name}-timer"));
+        }
+        builder
+    }
+}
+
+impl Builder {
+    /// Sets the number of workers. It must be greater than zero.
+    ///
+    /// With a [`max_size`](Builder::max_size), this is the core
+    /// size: the workers kept even when there is nothing to do.
+    pub fn size(mut self, size: usize) -> Builder {
+        self.size = Some(size);
+        self
+    }
+
+    /// Sets the number of workers to the number of CPUs the
+    /// process may use, as told by
+    /// [`std::thread::available_parallelism`], or one if unknown.
+    pub fn available_parallelism(self) -> Builder {
+        let size = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        self.size(size)
+    }
+
+    /// Lets the pool grow up to `max` workers while jobs wait and
+    /// every worker is busy. A size below the core size is ignored,
+    /// which is the default: the pool never grows.
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    ///
+    /// let pool = threadpool::ThreadPool::builder()
+    ///     .size(2)
+    ///     .max_size(8)
+    ///     .keep_alive(Duration::from_secs(10))
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(2, pool.stats().workers);
+    /// ```
+    pub fn max_size(mut self, max: usize) -> Builder {
+        self.max_size = Some(max);
+        self
+    }
+
+    /// Sets how long a worker beyond the core size may be idle
+    /// before it exits. It is a minute by default.
+    pub fn keep_alive(mut self, keep_alive: Duration) -> Builder {
+        self.keep_alive = Some(keep_alive);
+        self
+    }
+
+    /// Bounds the number of jobs waiting for a worker. Once it is
+    /// reached, [`ThreadPool::execute`] blocks and
+    /// [`ThreadPool::try_execute`] fails, which pushes back on the
+    /// producer instead of queueing without limit. Polls of woken
+    /// futures go past the bound, as their wakers may be called on a
+    /// worker. The queue is unbounded by default.
+    pub fn queue_capacity(mut self, capacity: usize) -> Builder {
+        self.queue_capacity = Some(capacity);
+        self
+    }
+
+    /// Names the worker threads `{name}-{id}`, as shown in panic
+    /// messages and debuggers. They are unnamed by default.
+    pub fn thread_name(mut self, name: impl Into<String>) -> Builder {
+        self.thread.name = Some(name.into());
+        self
+    }
+
+    /// Sets the stack size of the worker threads in bytes, instead
+    /// of the platform's default.
+    pub fn stack_size(mut self, size: usize) -> Builder {
+        self.thread.stack_size = Some(size);
+        self
+    }
+
+    /// Pins worker `id` to the CPU core `cores[id % cores.len()]`,
+    /// e.g. for benchmarks that should not depend on the scheduler.
+    ///
+    /// Only Linux supports it; elsewhere the workers are not pinned.
+    /// Spawning a worker fails if its core does not exist or the
+    /// process may not use it.
+    ///
+    /// Core 0 below is only an example: the process may be limited
+    /// to other cores, by `taskset` or a cgroup cpuset.
+    ///
+    /// ```rust,no_run
+    /// let pool = threadpool::ThreadPool::builder()
+    ///     .size(1)
+    ///     .pin_to_cores([0])
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn pin_to_cores(mut self, cores: impl IntoIterator<Item = usize>) -> Builder {
+        self.thread.cores = cores.into_iter().collect();
+        self
+    }
+
+    /// Sets the handler called when a job panics, see
+    /// [`ThreadPool::set_panic_handler`].
+    pub fn panic_handler<F>(mut self, handler: F) -> Builder
+    where
+        F: Fn(usize, Box<dyn Any + Send>) + Send + Sync + 'static,
+    {
+        self.panic_handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// Sets the listener receiving the pool's [`Event`](crate::Event)s.
+    /// Without one, the pool is silent.
+    pub fn listener(mut self, listener: impl Listener + 'static) -> Builder {
+        self.listener = Some(Arc::new(listener));
+        self
+    }
+
+    /// Creates the pool and starts its workers.
+    ///
+    /// It fails if the size is zero or missing, if the queue
+    /// capacity is zero, or if a worker thread cannot be spawned;
+    /// the workers already started are then shut down again.
+    pub fn build(self) -> Result<ThreadPool, PoolCreationError> {
+        let size = match self.size {
+            Some(size) if size > 0 => size,
+            _ => return Err(PoolCreationError::ZeroSize),
+        };
+        if self.queue_capacity == Some(0) {
+            return Err(PoolCreationError::ZeroCapacity);
+        }
+
+        let max = self.max_size.unwrap_or(size).max(size);
+
+        let shared = Arc::new(Shared {
+            queue: Queue::new(size, self.queue_capacity),
+            timer: Timer::new(),
+            workers: Mutex::new(Workers::new(size, max)),
+            exited: Condvar::new(),
+            alive: AtomicUsize::new(0),
+            core: AtomicUsize::new(size),
+            max: AtomicUsize::new(max),
+            keep_alive: self.keep_alive.unwrap_or(KEEP_ALIVE),
+            panic_handler: Mutex::new(self.panic_handler),
+            thread: self.thread,
+            listener: self.listener,
+            counters: Counters::default(),
+        });
+        let pool = ThreadPool { shared };
+
+        for _ in 0..size {
+            lock(&pool.shared.workers)
+                .spawn(&pool.shared)
+                .map_err(PoolCreationError::Spawn)?;
+        }
+
+        Ok(pool)
+    }
+}
+
+/// why [`Builder::build`] or [`ThreadPool::set_size`] failed.
+#[derive(Debug)]
+pub enum PoolCreationError {
+    /// the pool would have no worker to run jobs
+    ZeroSize,
+    /// the bounded queue could never take a job
+    ZeroCapacity,
+    /// the operating system refused to spawn a worker thread
+    Spawn(io::Error),
+}
+
+impl fmt::Display for PoolCreationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolCreationError::ZeroSize => write!(f, "pool size must be greater than zero"),
+            PoolCreationError::ZeroCapacity => {
+                write!(f, "queue capacity must be greater than zero")
+            }
+            PoolCreationError::Spawn(e) => write!(f, "failed to spawn a worker thread: {e}"),
+        }
+    }
+}
+
+impl Error for PoolCreationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PoolCreationError::ZeroSize | PoolCreationError::ZeroCapacity => None,
+            PoolCreationError::Spawn(e) => Some(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::affinity;
+    use std::thread;
+
+    #[test]
+    fn case_build() {
+        assert!(matches!(
+            ThreadPool::builder().build(),
+            Err(PoolCreationError::ZeroSize)
+        ));
+        assert!(matches!(
+            ThreadPool::builder().size(0).build(),
+            Err(PoolCreationError::ZeroSize)
+        ));
+
+        assert!(matches!(
+            ThreadPool::builder().size(1).queue_capacity(0).build(),
+            Err(PoolCreationError::ZeroCapacity)
+        ));
+
+        let pool = ThreadPool::builder()
+            .size(2)
+            .thread_name("test")
+            .build()
+            .unwrap();
+        assert_eq!(2, lock(&pool.shared.workers).alive());
+        let mut names: Vec<_> = (0..2)
+            .map(|id| {
+                let workers = lock(&pool.shared.workers);
+                let thread = workers.slots[id].as_ref().unwrap().thread();
+                thread.name().unwrap().to_string()
+            })
+            .collect();
+        names.sort();
+        assert_eq!(vec!["test-0", "test-1"], names);
+    }
+
+    #[test]
+    fn case_build_pinned() {
+        let core = affinity::cores()[0];
+        let pool = ThreadPool::builder()
+            .available_parallelism()
+            .pin_to_cores([core])
+            .build()
+            .unwrap();
+        assert!(pool.stats().workers >= 1);
+        assert_eq!(Some(core), pool.shared.thread.core(5));
+        assert_eq!(5, pool.spawn(|| 5).join().unwrap());
+
+        if cfg!(target_os = "linux") {
+            let err = ThreadPool::builder()
+                .size(1)
+                .pin_to_cores([usize::MAX])
+                .build()
+                .err()
+                .unwrap();
+            assert!(matches!(err, PoolCreationError::Spawn(_)));
+        }
+    }
+
+    #[test]
+    fn case_build_spawn_error() {
+        // no platform can give a thread a stack this large
+        let err = ThreadPool::builder()
+            .size(2)
+            .stack_size(usize::MAX)
+            .build()
+            .err()
+            .unwrap();
+        assert!(matches!(err, PoolCreationError::Spawn(_)));
+        assert!(err.source().is_some());
+
+        // the default config spawns unnamed threads
+        let pool = ThreadPool::builder().size(1).build().unwrap();
+        let name = pool.spawn(|| thread::current().name().map(String::from));
+        assert_eq!(None, name.join().unwrap());
+    }
+}
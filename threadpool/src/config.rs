@@ -0,0 +1,275 @@
+//! `config` gathers the settings of the server.
+//!
+//! Each setting in [`SETTINGS`] can come from three places, a later
+//! one overriding an earlier one:
+//!
+//! 1. the config file, `--config FILE` or `$SERVER_CONFIG`, made of
+//!    `name = value` lines; `#` starts a comment
+//! 2. the environment, e.g. `SERVER_PORT=8080`
+//! 3. the command line, e.g. `--port 8080` or `--port=8080`
+
+use std::fs;
+
+/// describes a setting.
+pub struct Setting {
+    /// the name in the config file and on the command line
+    pub name: &'static str,
+    /// the environment variable
+    pub env: &'static str,
+    /// the name of the value, for the help
+    pub value: &'static str,
+    pub help: &'static str,
+}
+
+pub const SETTINGS: &[Setting] = &[
+    Setting {
+        name: "address",
+        env: "SERVER_ADDRESS",
+        value: "HOST",
+        help: "the address to listen on [default: 127.0.0.1]",
+    },
+    Setting {
+        name: "port",
+        env: "SERVER_PORT",
+        value: "PORT",
+        help: "the port to listen on [default: 7878]",
+    },
+    Setting {
+        name: "workers",
+        env: "SERVER_WORKERS",
+        value: "N",
+        help: "the number of worker threads [default: the available parallelism]",
+    },
+    Setting {
+        name: "max-connections",
+        env: "SERVER_MAX_CONNECTIONS",
+        value: "N",
+        help: "the most connections open at once, the next get a 503 [default: no limit]",
+    },
+];
+
+/// the environment variable naming the config file.
+const CONFIG_ENV: &str = "SERVER_CONFIG";
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Config {
+    pub address: String,
+    pub port: u16,
+    /// `None` to size the pool from the available parallelism
+    pub workers: Option<usize>,
+    pub max_connections: Option<usize>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            address: "127.0.0.1".to_string(),
+            port: 7878,
+            workers: None,
+            max_connections: None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Run(Config),
+    Help,
+}
+
+impl Command {
+    /// Parses the command line, including the program name, on top
+    /// of the config file and the environment read by `env`.
+    pub fn parse(
+        mut args: impl Iterator<Item = String>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Command, String> {
+        args.next();
+
+        let mut file = env(CONFIG_ENV);
+        let mut overrides = Vec::new();
+        while let Some(arg) = args.next() {
+            let Some(option) = arg.strip_prefix("--") else {
+                return Err(format!("unexpected argument '{arg}'"));
+            };
+            let (name, inline) = match option.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (option, None),
+            };
+            if name == "help" {
+                return Ok(Command::Help);
+            }
+            let value = match inline {
+                Some(value) => value,
+                None => args
+                    .next()
+                    .ok_or_else(|| format!("missing the value of --{name}"))?,
+            };
+            match name {
+                "config" => file = Some(value),
+                _ => overrides.push((name.to_string(), value)),
+            }
+        }
+
+        let mut config = Config::default();
+        if let Some(file) = file {
+            config.read_file(&file)?;
+        }
+        for setting in SETTINGS {
+            if let Some(value) = env(setting.env) {
+                config
+                    .set(setting.name, &value)
+                    .map_err(|e| format!("${}: {e}", setting.env))?;
+            }
+        }
+        for (name, value) in overrides {
+            config
+                .set(&name, &value)
+                .map_err(|e| format!("--{name}: {e}"))?;
+        }
+        Ok(Command::Run(config))
+    }
+}
+
+impl Config {
+    fn read_file(&mut self, path: &str) -> Result<(), String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((name, value)) = line.split_once('=') else {
+                return Err(format!("{path}:{}: expected 'name = value'", i + 1));
+            };
+            self.set(name.trim(), value.trim())
+                .map_err(|e| format!("{path}:{}: {e}", i + 1))?;
+        }
+        Ok(())
+    }
+
+    /// sets the setting `name` from `value`.
+    fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let count = |value: &str| match value.parse() {
+            Ok(0) | Err(_) => Err(format!(
+                "invalid {name} '{value}', expected a positive number"
+            )),
+            Ok(n) => Ok(Some(n)),
+        };
+        match name {
+            "address" if value.is_empty() => return Err("the address is empty".to_string()),
+            "address" => self.address = value.to_string(),
+            "port" => {
+                self.port = value
+                    .parse()
+                    .map_err(|_| format!("invalid port '{value}'"))?;
+            }
+            "workers" => self.workers = count(value)?,
+            "max-connections" => self.max_connections = count(value)?,
+            _ => return Err(format!("unknown setting '{name}'")),
+        }
+        Ok(())
+    }
+}
+
+/// the help printed by `--help`.
+pub fn help(program: &str) -> String {
+    let mut s = format!("Usage: {program} [--config FILE] [--NAME VALUE]...\n\nSettings:\n");
+    for setting in SETTINGS {
+        let option = format!("--{} {}", setting.name, setting.value);
+        s.push_str(&format!("  {option:<24} {}\n", setting.help));
+        s.push_str(&format!("  {:<24} or ${}\n", "", setting.env));
+    }
+    s.push_str(&format!(
+        "  {:<24} read the settings from FILE, or ${CONFIG_ENV}\n",
+        "--config FILE"
+    ));
+    s.push_str(&format!("  {:<24} print this help\n", "--help"));
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn parse(cmd: &str, env: &[(&str, &str)]) -> Result<Command, String> {
+        let env: HashMap<_, _> = env
+            .iter()
+            .map(|&(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let args = cmd.split_whitespace().map(String::from);
+        Command::parse(args, |name| env.get(name).cloned())
+    }
+
+    fn run(cmd: &str, env: &[(&str, &str)]) -> Config {
+        match parse(cmd, env) {
+            Ok(Command::Run(config)) => config,
+            other => panic!("{cmd}: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn case_parse() {
+        assert_eq!(Config::default(), run("server", &[]));
+        assert_eq!(Ok(Command::Help), parse("server --port 1 --help", &[]));
+
+        let config = run(
+            "server --address 0.0.0.0 --port=80 --workers 8 --max-connections=100",
+            &[],
+        );
+        let expected = Config {
+            address: "0.0.0.0".to_string(),
+            port: 80,
+            workers: Some(8),
+            max_connections: Some(100),
+        };
+        assert_eq!(expected, config);
+
+        for (cmd, err) in [
+            ("server --port", "missing the value of --port"),
+            ("server --port 70000", "--port: invalid port '70000'"),
+            (
+                "server --workers 0",
+                "--workers: invalid workers '0', expected a positive number",
+            ),
+            ("server --threads 2", "--threads: unknown setting 'threads'"),
+            ("server 8080", "unexpected argument '8080'"),
+        ] {
+            assert_eq!(Err(err.to_string()), parse(cmd, &[]), "{cmd}");
+        }
+    }
+
+    #[test]
+    fn case_parse_sources() {
+        let dir = std::env::temp_dir().join(format!("threadpool-config-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("server.conf");
+        let contents = "# the server\naddress = 0.0.0.0\nport = 8000 # http\n\nworkers=2\n";
+        fs::write(&file, contents).unwrap();
+        let file = file.to_str().unwrap();
+
+        // the file, then the environment, then the command line
+        let env = [(CONFIG_ENV, file), ("SERVER_PORT", "9000")];
+        let config = run("server --workers 3", &env);
+        assert_eq!(
+            ("0.0.0.0", 9000, Some(3)),
+            (&config.address[..], config.port, config.workers)
+        );
+        let config = run(&format!("server --config {file}"), &[]);
+        assert_eq!(8000, config.port);
+
+        let err = parse("server", &[("SERVER_MAX_CONNECTIONS", "lots")]).unwrap_err();
+        assert_eq!(
+            "$SERVER_MAX_CONNECTIONS: invalid max-connections 'lots', expected a positive number",
+            err
+        );
+        fs::write(dir.join("bad.conf"), "port = 1\nport 2\n").unwrap();
+        let bad = dir.join("bad.conf");
+        let err = parse(&format!("server --config {}", bad.display()), &[]).unwrap_err();
+        assert_eq!(format!("{}:2: expected 'name = value'", bad.display()), err);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,240 @@
+//! `future` polls [`Future`]s on the workers.
+//!
+//! A spawned future becomes a task. Each poll of the task is a job:
+//! when the future is pending, the job ends and the worker goes on
+//! with other jobs. Waking the task queues a new job to poll it
+//! again, so no worker is blocked while the future waits.
+
+use crate::join::{self, JoinHandle};
+use crate::queue::{Priority, Wait};
+use crate::{lock, Job, Outcome, Shared, ThreadPool};
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Wake, Waker};
+
+/// waiting for a wake-up
+const IDLE: u8 = 0;
+/// queued to be polled
+const SCHEDULED: u8 = 1;
+/// being polled
+const RUNNING: u8 = 2;
+/// woken while being polled, so it must be polled again
+const NOTIFIED: u8 = 3;
+/// the future has completed
+const DONE: u8 = 4;
+
+type BoxFuture = Pin<Box<dyn Future<Output = Outcome> + Send>>;
+
+struct Task {
+    future: Mutex<Option<BoxFuture>>,
+    state: AtomicU8,
+    /// weak, so that a waker kept somewhere does not keep the pool
+    shared: Weak<Shared>,
+}
+
+impl Task {
+    /// queues a poll of the task. Wakes pass `Wait::Over`: a waker
+    /// is often called on a worker, and a worker waiting for room in
+    /// a full queue may be the one that would have made it.
+    fn schedule(self: Arc<Task>, wait: Wait) {
+        if let Some(shared) = self.shared.upgrade() {
+            let into_job = |task: Arc<Task>| -> Job { Box::new(move || task.run()) };
+            let _ = shared.send(self, into_job, wait, Priority::Normal);
+        }
+    }
+
+    /// polls the future once.
+    fn run(self: Arc<Task>) -> Outcome {
+        self.state.store(RUNNING, Ordering::SeqCst);
+        let waker = Waker::from(Arc::clone(&self));
+        let mut cx = Context::from_waker(&waker);
+
+        let mut future = lock(&self.future);
+        let Some(polled) = future.as_mut() else {
+            return Outcome::Pending;
+        };
+        if let Poll::Ready(outcome) = polled.as_mut().poll(&mut cx) {
+            *future = None;
+            self.state.store(DONE, Ordering::SeqCst);
+            return outcome;
+        }
+        drop(future);
+
+        let idle = self
+            .state
+            .compare_exchange(RUNNING, IDLE, Ordering::SeqCst, Ordering::SeqCst);
+        if idle.is_err() {
+            // woken while polled: poll again, behind the queued jobs
+            self.state.store(SCHEDULED, Ordering::SeqCst);
+            self.schedule(Wait::Over);
+        }
+        Outcome::Pending
+    }
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Task>) {
+        let mut state = self.state.load(Ordering::SeqCst);
+        loop {
+            let next = match state {
+                IDLE => SCHEDULED,
+                RUNNING => NOTIFIED,
+                _ => return,
+            };
+            match self
+                .state
+                .compare_exchange(state, next, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) if next == SCHEDULED => return self.schedule(Wait::Over),
+                Ok(_) => return,
+                Err(actual) => state = actual,
+            }
+        }
+    }
+}
+
+/// polls the inner future, catching a panic.
+struct CatchUnwind<F>(Pin<Box<F>>);
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = std::thread::Result<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let inner = self.0.as_mut();
+        match panic::catch_unwind(AssertUnwindSafe(|| inner.poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    }
+}
+
+impl ThreadPool {
+    /// Runs `future` on the workers, and returns a handle to wait
+    /// for its output.
+    ///
+    /// The future is polled by a worker until it is pending, then
+    /// again whenever it is woken. A panic while polling ends the
+    /// future; `join()` returns it as an error. A future still
+    /// waiting when the pool shuts down is not polled again.
+    ///
+    /// ```rust
+    /// let pool = threadpool::ThreadPool::new(2);
+    ///
+    /// let squared = pool.spawn(|| 6 * 6);
+    /// let handle = pool.spawn_future(async move { squared.await.unwrap() + 6 });
+    /// assert_eq!(42, handle.join().unwrap());
+    /// ```
+    pub fn spawn_future<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let (handle, completer) = join::pair();
+        let future = CatchUnwind(Box::pin(future));
+        let future = async move { completer.finish(future.await) };
+
+        let task = Arc::new(Task {
+            future: Mutex::new(Some(Box::pin(future))),
+            state: AtomicU8::new(SCHEDULED),
+            shared: Arc::downgrade(&self.shared),
+        });
+        task.schedule(Wait::Forever);
+        handle
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    /// pending the first `n` times, waking itself from `wake`.
+    struct Countdown<W> {
+        n: usize,
+        wake: W,
+    }
+
+    impl<W: Fn(Waker) + Unpin> Future for Countdown<W> {
+        type Output = &'static str;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<&'static str> {
+            if self.n == 0 {
+                return Poll::Ready("done");
+            }
+            self.n -= 1;
+            (self.wake)(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    #[test]
+    fn case_spawn_future() {
+        let pool = ThreadPool::new(1);
+
+        // woken while it is being polled
+        let handle = pool.spawn_future(Countdown {
+            n: 3,
+            wake: Waker::wake,
+        });
+        assert_eq!("done", handle.join().unwrap());
+
+        // woken later by another thread
+        let handle = pool.spawn_future(Countdown {
+            n: 2,
+            wake: |waker: Waker| {
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(10));
+                    waker.wake();
+                });
+            },
+        });
+        assert_eq!("done", handle.join().unwrap());
+
+        // the worker is free while the future waits, or the job
+        // completing it could never run
+        let (handle, completer) = join::pair();
+        let waiting = pool.spawn_future(async move { handle.await.unwrap() * 2 });
+        pool.execute(move || {
+            completer.run(|| 21);
+        });
+        assert_eq!(42, waiting.join().unwrap());
+    }
+
+    #[test]
+    fn case_spawn_future_bounded() {
+        let pool = ThreadPool::builder()
+            .size(2)
+            .queue_capacity(1)
+            .build()
+            .unwrap();
+
+        // each poll wakes the future from a worker while the queue is
+        // full of the others; waiting for room there would leave no
+        // worker to make it
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                pool.spawn_future(Countdown {
+                    n: 50,
+                    wake: Waker::wake,
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!("done", handle.join().unwrap());
+        }
+    }
+
+    #[test]
+    fn case_spawn_future_panic() {
+        let pool = ThreadPool::new(1);
+        let handle = pool.spawn_future(async { panic!("boom") });
+        let err = handle.join().unwrap_err();
+        assert_eq!("job panicked: boom", err.to_string());
+        assert_eq!("ok", pool.spawn_future(async { "ok" }).join().unwrap());
+    }
+}
@@ -0,0 +1,461 @@
+//! `files` serves the files under a directory.
+//!
+//! The path asked for is decoded and checked before it touches the
+//! file system: no `..` or `.` segments, and once symbolic links are
+//! resolved the file must still be under the directory. Anything
+//! else is answered as not found, so a client cannot tell what
+//! exists outside.
+//!
+//! Responses carry an `ETag`, built from the size and modification
+//! time, and a `Last-Modified`, so a client can revalidate with
+//! `If-None-Match` or `If-Modified-Since` and get a 304. A single
+//! `Range` of bytes is answered with a 206; several ranges get the
+//! whole file, as RFC 9110 allows. The body is copied from the file as
+//! it is sent, never read in memory whole.
+
+use super::{Request, Response};
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Answers `request` with the file at `path` under `root`.
+///
+/// `path` is relative to `root` and still percent-encoded, as it
+/// comes in the request target.
+pub fn serve(root: &Path, request: &Request, path: &str) -> Response {
+    let Some(path) = resolve(root, path) else {
+        return Response::error(404, "no such file");
+    };
+    match serve_file(&path, request) {
+        Ok(response) => response,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Response::error(404, "no such file"),
+        Err(e) => Response::error(500, &format!("failed to read the file: {e}")),
+    }
+}
+
+fn serve_file(path: &Path, request: &Request) -> io::Result<Response> {
+    let mut file = File::open(path)?;
+    let metadata = file.metadata()?;
+    if !metadata.is_file() {
+        return Err(io::ErrorKind::NotFound.into());
+    }
+    let len = metadata.len();
+    let modified = metadata.modified().ok().map(seconds);
+    let etag = format!("\"{len:x}-{:x}\"", modified.unwrap_or(0));
+    let last_modified = modified.map(http_date);
+
+    let mut response = Response::new(200).header("ETag", &etag);
+    if let Some(last_modified) = &last_modified {
+        response = response.header("Last-Modified", last_modified);
+    }
+    if not_modified(request, &etag, modified) {
+        return Ok(response.status(304));
+    }
+    response = response
+        .header("Content-Type", content_type(path))
+        .header("Accept-Ranges", "bytes");
+
+    // a range of an older version of the file is not wanted
+    let current = match request.headers.get("If-Range") {
+        Some(validator) => validator == etag || Some(validator) == last_modified.as_deref(),
+        None => true,
+    };
+    let range = match request.headers.get("Range") {
+        Some(value) if current => parse_range(value, len),
+        _ => Range::Whole,
+    };
+
+    match range {
+        Range::Whole => Ok(response.file(file, len)),
+        Range::Part(start, end) => {
+            file.seek(SeekFrom::Start(start))?;
+            let content_range = format!("bytes {start}-{end}/{len}");
+            Ok(response
+                .status(206)
+                .header("Content-Range", &content_range)
+                .file(file, end - start + 1))
+        }
+        Range::Unsatisfiable => {
+            let content_range = format!("bytes */{len}");
+            Ok(Response::error(416, "the range is outside the file")
+                .header("Content-Range", &content_range))
+        }
+    }
+}
+
+/// maps `path`, still percent-encoded, to a file under `root`.
+fn resolve(root: &Path, path: &str) -> Option<PathBuf> {
+    let path = percent_decode(path)?;
+    let unsafe_segment = |segment: &str| {
+        segment.is_empty() || segment == "." || segment == ".." || segment.contains(['\\', '\0'])
+    };
+    if path.split('/').any(unsafe_segment) {
+        return None;
+    }
+
+    let root = root.canonicalize().ok()?;
+    let path = root.join(path).canonicalize().ok()?;
+    path.starts_with(&root).then_some(path)
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let mut decoded = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        if b != b'%' {
+            decoded.push(b);
+            continue;
+        }
+        let hex = [bytes.next()?, bytes.next()?];
+        if !hex.iter().all(u8::is_ascii_hexdigit) {
+            return None;
+        }
+        let hex = std::str::from_utf8(&hex).ok()?;
+        decoded.push(u8::from_str_radix(hex, 16).ok()?);
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// returns true if the client's copy, named by `If-None-Match` or
+/// dated by `If-Modified-Since`, is still current.
+fn not_modified(request: &Request, etag: &str, modified: Option<u64>) -> bool {
+    // a weak comparison: `W/"x"` matches `"x"`
+    if let Some(tags) = request.headers.get("If-None-Match") {
+        return tags.split(',').any(|tag| {
+            let tag = tag.trim();
+            tag == "*" || tag.trim_start_matches("W/") == etag
+        });
+    }
+    let since = request
+        .headers
+        .get("If-Modified-Since")
+        .and_then(parse_http_date);
+    match (since, modified) {
+        (Some(since), Some(modified)) => modified <= since,
+        _ => false,
+    }
+}
+
+/// the bytes asked for by a `Range` header.
+#[derive(Debug, PartialEq, Eq)]
+enum Range {
+    /// no valid single range: the whole file
+    Whole,
+    /// from the first byte to the last, included
+    Part(u64, u64),
+    /// a valid range, but past the end of the file
+    Unsatisfiable,
+}
+
+fn parse_range(value: &str, len: u64) -> Range {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return Range::Whole;
+    };
+    if spec.contains(',') {
+        return Range::Whole;
+    }
+    let Some((first, last)) = spec.split_once('-') else {
+        return Range::Whole;
+    };
+    let number = |s: &str| {
+        let s = s.trim();
+        let digits = !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+        digits.then(|| s.parse::<u64>().ok()).flatten()
+    };
+
+    if first.trim().is_empty() {
+        // the last `n` bytes
+        return match number(last) {
+            Some(0) => Range::Unsatisfiable,
+            Some(_) if len == 0 => Range::Unsatisfiable,
+            Some(n) => Range::Part(len - n.min(len), len - 1),
+            None => Range::Whole,
+        };
+    }
+    let Some(first) = number(first) else {
+        return Range::Whole;
+    };
+    let last = match last.trim() {
+        "" => None,
+        last => match number(last) {
+            Some(last) if last >= first => Some(last),
+            _ => return Range::Whole,
+        },
+    };
+    if first >= len {
+        return Range::Unsatisfiable;
+    }
+    Range::Part(first, last.map_or(len - 1, |last| last.min(len - 1)))
+}
+
+fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "txt" => "text/plain; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "pdf" => "application/pdf",
+        "wasm" => "application/wasm",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "webp" => "image/webp",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+/// seconds since the epoch, 0 for a time before it.
+fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// formats `seconds` since the epoch as an HTTP date, e.g.
+/// `Sun, 06 Nov 1994 08:49:37 GMT`.
+fn http_date(seconds: u64) -> String {
+    let days = (seconds / 86400) as i64;
+    let time = seconds % 86400;
+    let (year, month, day) = civil_from_days(days);
+    // the epoch was a Thursday
+    let weekday = WEEKDAYS[((days + 4) % 7) as usize];
+    let month = MONTHS[month as usize - 1];
+    let (hour, minute, second) = (time / 3600, time / 60 % 60, time % 60);
+    format!("{weekday}, {day:02} {month} {year} {hour:02}:{minute:02}:{second:02} GMT")
+}
+
+/// parses an HTTP date, as `http_date` formats it, into seconds
+/// since the epoch.
+fn parse_http_date(date: &str) -> Option<u64> {
+    let (_weekday, date) = date.split_once(", ")?;
+    let mut parts = date.split(' ');
+    let (Some(day), Some(month), Some(year), Some(time), Some("GMT"), None) = (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    ) else {
+        return None;
+    };
+    let day: i64 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|&name| name == month)? as i64 + 1;
+    let year: i64 = year.parse().ok()?;
+    let mut time = time.split(':').map(|part| part.parse::<u64>().ok());
+    let (Some(Some(hour)), Some(Some(minute)), Some(Some(second)), None) =
+        (time.next(), time.next(), time.next(), time.next())
+    else {
+        return None;
+    };
+    if !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    Some(days * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+// The two conversions below are Howard Hinnant's, for the proleptic
+// Gregorian calendar with years starting in March.
+
+/// the (year, month, day) of `days` since the epoch.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// the days since the epoch of `year`-`month`-`day`.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// a directory with `hello.txt`, `image.png` and `sub/page.html`,
+    /// next to `secret.txt` which must not be served.
+    fn setup(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("threadpool-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let root = dir.join("root");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("hello.txt"), "hello, world").unwrap();
+        fs::write(root.join("image.png"), [0x89, b'P', b'N', b'G', 0, 0xff]).unwrap();
+        fs::write(root.join("sub/page.html"), "<p>page</p>").unwrap();
+        fs::write(dir.join("secret.txt"), "secret").unwrap();
+        root
+    }
+
+    fn get(root: &Path, path: &str, headers: &str) -> String {
+        let raw = format!("GET /{path} HTTP/1.1\r\nHost: a\r\n{headers}\r\n");
+        let request = Request::read(&mut raw.as_bytes()).unwrap().unwrap();
+        let mut out = Vec::new();
+        serve(root, &request, path).write_to(&mut out).unwrap();
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    /// the value of header `name` in the response `raw`.
+    fn header<'a>(raw: &'a str, name: &str) -> Option<&'a str> {
+        let head = raw.split("\r\n\r\n").next().unwrap();
+        head.lines()
+            .filter_map(|line| line.split_once(": "))
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
+
+    #[test]
+    fn case_serve() {
+        let root = setup("serve");
+
+        let ok = get(&root, "hello.txt", "");
+        assert!(ok.starts_with("HTTP/1.1 200 OK\r\n"), "{ok}");
+        assert!(ok.ends_with("\r\n\r\nhello, world"));
+        assert_eq!(
+            Some("text/plain; charset=utf-8"),
+            header(&ok, "Content-Type")
+        );
+        assert_eq!(Some("bytes"), header(&ok, "Accept-Ranges"));
+
+        let page = get(&root, "sub/page%2ehtml", "");
+        assert_eq!(
+            Some("text/html; charset=utf-8"),
+            header(&page, "Content-Type")
+        );
+
+        let image = get(&root, "image.png", "");
+        assert_eq!(Some("image/png"), header(&image, "Content-Type"));
+        assert_eq!(Some("6"), header(&image, "Content-Length"));
+
+        for path in [
+            "../secret.txt",
+            "sub/../../secret.txt",
+            "%2e%2e/secret.txt",
+            "sub/..%2f..%2fsecret.txt",
+            "sub",
+            "sub//page.html",
+            "missing.txt",
+            "bad%zz",
+        ] {
+            let response = get(&root, path, "");
+            assert!(response.starts_with("HTTP/1.1 404 "), "{path}: {response}");
+        }
+
+        fs::remove_dir_all(root.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn case_serve_not_modified() {
+        let root = setup("cache");
+        let ok = get(&root, "hello.txt", "");
+        let etag = header(&ok, "ETag").unwrap();
+        let last_modified = header(&ok, "Last-Modified").unwrap();
+
+        let cached = get(&root, "hello.txt", &format!("If-None-Match: W/{etag}\r\n"));
+        assert!(
+            cached.starts_with("HTTP/1.1 304 Not Modified\r\n"),
+            "{cached}"
+        );
+        assert_eq!(Some(etag), header(&cached, "ETag"));
+        assert_eq!(None, header(&cached, "Content-Length"));
+        assert!(cached.ends_with("\r\n\r\n"));
+
+        let stale = get(&root, "hello.txt", "If-None-Match: \"other\"\r\n");
+        assert!(stale.starts_with("HTTP/1.1 200 "));
+
+        let since = format!("If-Modified-Since: {last_modified}\r\n");
+        assert!(get(&root, "hello.txt", &since).starts_with("HTTP/1.1 304 "));
+        let since = "If-Modified-Since: Thu, 01 Jan 1970 00:00:00 GMT\r\n";
+        assert!(get(&root, "hello.txt", since).starts_with("HTTP/1.1 200 "));
+
+        fs::remove_dir_all(root.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn case_serve_range() {
+        let root = setup("range");
+
+        let part = get(&root, "hello.txt", "Range: bytes=7-\r\n");
+        assert!(
+            part.starts_with("HTTP/1.1 206 Partial Content\r\n"),
+            "{part}"
+        );
+        assert_eq!(Some("bytes 7-11/12"), header(&part, "Content-Range"));
+        assert!(part.ends_with("\r\n\r\nworld"));
+
+        let etag = header(&part, "ETag").unwrap();
+        let current = format!("Range: bytes=0-4\r\nIf-Range: {etag}\r\n");
+        assert!(get(&root, "hello.txt", &current).ends_with("\r\n\r\nhello"));
+        let changed = "Range: bytes=0-4\r\nIf-Range: \"old\"\r\n";
+        assert!(get(&root, "hello.txt", changed).starts_with("HTTP/1.1 200 "));
+
+        let out = get(&root, "hello.txt", "Range: bytes=12-\r\n");
+        assert!(out.starts_with("HTTP/1.1 416 "), "{out}");
+        assert_eq!(Some("bytes */12"), header(&out, "Content-Range"));
+
+        let len = 12;
+        assert_eq!(Range::Part(0, 0), parse_range("bytes=0-0", len));
+        assert_eq!(Range::Part(2, 11), parse_range("bytes=2-100", len));
+        assert_eq!(Range::Part(7, 11), parse_range("bytes=-5", len));
+        assert_eq!(Range::Part(0, 11), parse_range("bytes=-50", len));
+        assert_eq!(Range::Unsatisfiable, parse_range("bytes=-0", len));
+        assert_eq!(Range::Unsatisfiable, parse_range("bytes=0-", 0));
+        for whole in [
+            "bytes=0-1,3-4",
+            "bytes=5-2",
+            "bytes=a-",
+            "items=0-1",
+            "bytes=-",
+        ] {
+            assert_eq!(Range::Whole, parse_range(whole, len), "{whole}");
+        }
+
+        fs::remove_dir_all(root.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn case_http_date() {
+        let date = "Sun, 06 Nov 1994 08:49:37 GMT";
+        assert_eq!(date, http_date(784_111_777));
+        assert_eq!(Some(784_111_777), parse_http_date(date));
+        assert_eq!("Thu, 01 Jan 1970 00:00:00 GMT", http_date(0));
+        assert_eq!("Tue, 29 Feb 2000 23:59:59 GMT", http_date(951_868_799));
+        assert_eq!(Some(951_868_799), parse_http_date(&http_date(951_868_799)));
+        assert_eq!(None, parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"));
+        assert_eq!(None, parse_http_date("Sun, 06 Nov 1994 08:49:37 UTC"));
+    }
+}
@@ -0,0 +1,12 @@
+//! `http` speaks just enough HTTP/1.1 for the server: it parses
+//! requests from a connection, routes them to their handlers and
+//! writes the responses back. `files` serves a static directory.
+
+pub mod files;
+mod request;
+mod response;
+mod router;
+
+pub use request::Request;
+pub use response::Response;
+pub use router::{Match, Params, Router};
@@ -0,0 +1,494 @@
+//! `request` parses an HTTP/1.1 request from a connection.
+//!
+//! It follows RFC 9112 where a mistake could confuse the server
+//! about where a request ends: bad framing is an error, never a
+//! guess. Lines may end in `\r\n` or a bare `\n`.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Read};
+
+/// the longest request line or header line accepted.
+const MAX_LINE: usize = 8 * 1024;
+/// the most header lines, or trailer lines, accepted.
+const MAX_HEADERS: usize = 100;
+/// the largest body accepted.
+const MAX_BODY: usize = 1024 * 1024;
+
+#[derive(Debug)]
+pub struct Request {
+    /// e.g. `GET`, case-sensitive
+    pub method: String,
+    /// the target up to `?`, e.g. `/users/1`
+    pub path: String,
+    /// the target after `?`, e.g. `page=2`
+    pub query: Option<String>,
+    pub version: Version,
+    pub headers: Headers,
+    /// the body, with the chunked encoding removed
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+/// the header fields of a request, in the order received.
+#[derive(Debug, Default)]
+pub struct Headers(Vec<(String, String)>);
+
+impl Headers {
+    /// returns the first value of the field `name`, ignoring case.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        let (_, value) = self
+            .0
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))?;
+        Some(value)
+    }
+
+    /// returns every value of the field `name`, ignoring case.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.0
+            .iter()
+            .filter(move |(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// why a request could not be parsed.
+#[derive(Debug)]
+pub enum ParseError {
+    /// the connection failed or closed in the middle of a request
+    Io(io::Error),
+    /// the request line is not `METHOD /target HTTP/1.x`
+    RequestLine,
+    /// the request is for an HTTP version other than 1.0 or 1.1
+    Version,
+    /// a header line is not `name: value`
+    Header,
+    /// an HTTP/1.1 request has no `Host` header
+    MissingHost,
+    /// `Content-Length` is not a number, or is given twice
+    /// differently
+    ContentLength,
+    /// both `Content-Length` and `Transfer-Encoding` are given, so
+    /// the end of the body is ambiguous
+    Framing,
+    /// the transfer codings are anything but a single `chunked`
+    TransferEncoding,
+    /// a chunk of a chunked body is malformed
+    Chunk,
+    /// a line is too long, or there are too many headers
+    HeadersTooLarge,
+    /// the body is larger than the server accepts
+    BodyTooLarge,
+}
+
+impl ParseError {
+    /// the status code of the response to send back.
+    pub fn status(&self) -> u16 {
+        match self {
+            ParseError::Io(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                408
+            }
+            ParseError::Version => 505,
+            ParseError::TransferEncoding => 501,
+            ParseError::HeadersTooLarge => 431,
+            ParseError::BodyTooLarge => 413,
+            _ => 400,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Io(e) => write!(f, "failed to read the request: {e}"),
+            ParseError::RequestLine => write!(f, "malformed request line"),
+            ParseError::Version => write!(f, "unsupported HTTP version"),
+            ParseError::Header => write!(f, "malformed header"),
+            ParseError::MissingHost => write!(f, "missing Host header"),
+            ParseError::ContentLength => write!(f, "invalid Content-Length"),
+            ParseError::Framing => write!(f, "both Content-Length and Transfer-Encoding"),
+            ParseError::TransferEncoding => write!(f, "unsupported Transfer-Encoding"),
+            ParseError::Chunk => write!(f, "malformed chunked body"),
+            ParseError::HeadersTooLarge => write!(f, "request header fields too large"),
+            ParseError::BodyTooLarge => write!(f, "request body too large"),
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> ParseError {
+        ParseError::Io(e)
+    }
+}
+
+impl Request {
+    /// Reads the next request from `reader`.
+    ///
+    /// Returns `None` if the connection is closed before a request
+    /// starts.
+    pub fn read(reader: &mut impl BufRead) -> Result<Option<Request>, ParseError> {
+        // a client may send empty lines between requests
+        let line = loop {
+            match read_line(reader)? {
+                None => return Ok(None),
+                Some(line) if line.is_empty() => continue,
+                Some(line) => break line,
+            }
+        };
+        let mut request = parse_request_line(&line)?;
+
+        request.headers = read_fields(reader)?;
+        if request.version == Version::Http11 && request.headers.get("Host").is_none() {
+            return Err(ParseError::MissingHost);
+        }
+        request.body = read_body(reader, &request.headers)?;
+        Ok(Some(request))
+    }
+
+    /// returns true if the client wants the connection kept open
+    /// after the response: HTTP/1.1 does unless it says `close`,
+    /// HTTP/1.0 only if it says `keep-alive`.
+    pub fn keep_alive(&self) -> bool {
+        let says = |option: &str| {
+            self.headers
+                .get_all("Connection")
+                .flat_map(|value| value.split(','))
+                .any(|token| token.trim().eq_ignore_ascii_case(option))
+        };
+        match self.version {
+            Version::Http11 => !says("close"),
+            Version::Http10 => says("keep-alive"),
+        }
+    }
+}
+
+/// reads a line without its ending. Returns `None` at the end of
+/// the stream.
+fn read_line(reader: &mut impl BufRead) -> Result<Option<String>, ParseError> {
+    let mut line = Vec::new();
+    let limit = MAX_LINE as u64 + 2;
+    let n = reader.by_ref().take(limit).read_until(b'\n', &mut line)?;
+    if n == 0 {
+        return Ok(None);
+    }
+    if line.pop() != Some(b'\n') {
+        if n as u64 == limit {
+            return Err(ParseError::HeadersTooLarge);
+        }
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    if line.len() > MAX_LINE {
+        return Err(ParseError::HeadersTooLarge);
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| ParseError::Header)
+}
+
+/// reads a line which must be there, as in the middle of a request.
+fn expect_line(reader: &mut impl BufRead) -> Result<String, ParseError> {
+    read_line(reader)?.ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof).into())
+}
+
+fn parse_request_line(line: &str) -> Result<Request, ParseError> {
+    let mut parts = line.split(' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(ParseError::RequestLine);
+    };
+    if !is_token(method) || !target.starts_with('/') || target.contains(char::is_whitespace) {
+        return Err(ParseError::RequestLine);
+    }
+    let version = match version {
+        "HTTP/1.1" => Version::Http11,
+        "HTTP/1.0" => Version::Http10,
+        v if v.starts_with("HTTP/") => return Err(ParseError::Version),
+        _ => return Err(ParseError::RequestLine),
+    };
+
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, Some(query.to_string())),
+        None => (target, None),
+    };
+    Ok(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query,
+        version,
+        headers: Headers::default(),
+        body: Vec::new(),
+    })
+}
+
+/// reads header or trailer fields up to the empty line.
+fn read_fields(reader: &mut impl BufRead) -> Result<Headers, ParseError> {
+    let mut fields = Vec::new();
+    loop {
+        let line = expect_line(reader)?;
+        if line.is_empty() {
+            return Ok(Headers(fields));
+        }
+        if fields.len() == MAX_HEADERS {
+            return Err(ParseError::HeadersTooLarge);
+        }
+        // no space is allowed before the colon, and a line folded
+        // onto the previous one is obsolete
+        let (name, value) = line.split_once(':').ok_or(ParseError::Header)?;
+        if !is_token(name) {
+            return Err(ParseError::Header);
+        }
+        let value = value.trim_matches([' ', '\t']);
+        fields.push((name.to_string(), value.to_string()));
+    }
+}
+
+fn read_body(reader: &mut impl BufRead, headers: &Headers) -> Result<Vec<u8>, ParseError> {
+    let mut lengths = headers.get_all("Content-Length");
+    let length = match lengths.next() {
+        Some(first) => {
+            if lengths.any(|other| other != first) {
+                return Err(ParseError::ContentLength);
+            }
+            Some(parse_length(first)?)
+        }
+        None => None,
+    };
+
+    // every coding of every `Transfer-Encoding` header counts, or
+    // a proxy reading another one could split the requests elsewhere
+    let codings: Vec<&str> = headers
+        .get_all("Transfer-Encoding")
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+    if !codings.is_empty() {
+        if length.is_some() {
+            return Err(ParseError::Framing);
+        }
+        if !matches!(codings[..], [coding] if coding.eq_ignore_ascii_case("chunked")) {
+            return Err(ParseError::TransferEncoding);
+        }
+        return read_chunked(reader);
+    }
+
+    let length = length.unwrap_or(0);
+    if length > MAX_BODY {
+        return Err(ParseError::BodyTooLarge);
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(body)
+}
+
+fn parse_length(value: &str) -> Result<usize, ParseError> {
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(ParseError::ContentLength);
+    }
+    // a length too large for usize is too large for the server too
+    value.parse().map_err(|_| ParseError::BodyTooLarge)
+}
+
+fn read_chunked(reader: &mut impl BufRead) -> Result<Vec<u8>, ParseError> {
+    let mut body = Vec::new();
+    loop {
+        let line = expect_line(reader)?;
+        // chunk extensions after `;` are ignored
+        let size = line.split(';').next().unwrap_or("").trim_end();
+        if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(ParseError::Chunk);
+        }
+        let size = usize::from_str_radix(size, 16).map_err(|_| ParseError::Chunk)?;
+        if size == 0 {
+            read_fields(reader)?;
+            return Ok(body);
+        }
+        if size > MAX_BODY - body.len() {
+            return Err(ParseError::BodyTooLarge);
+        }
+
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..])?;
+        if !expect_line(reader)?.is_empty() {
+            return Err(ParseError::Chunk);
+        }
+    }
+}
+
+/// checks `s` is an RFC 9110 token, as method and field names are.
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &str) -> Result<Option<Request>, ParseError> {
+        Request::read(&mut raw.as_bytes())
+    }
+
+    #[test]
+    fn case_parse() {
+        let raw = "\r\nGET /users/1?page=2&x HTTP/1.1\r\nHost: localhost\r\n\
+                   Accept:  text/html \r\nX-Tag: a\r\nx-tag: b\r\n\r\n";
+        let request = parse(raw).unwrap().unwrap();
+        assert_eq!("GET", request.method);
+        assert_eq!("/users/1", request.path);
+        assert_eq!(Some("page=2&x"), request.query.as_deref());
+        assert_eq!(Version::Http11, request.version);
+        assert_eq!(Some("text/html"), request.headers.get("accept"));
+        let tags: Vec<_> = request.headers.get_all("X-Tag").collect();
+        assert_eq!(vec!["a", "b"], tags);
+        assert!(request.body.is_empty());
+
+        // HTTP/1.0 needs no Host, and lines may end in a bare `\n`
+        let request = parse("GET / HTTP/1.0\n\n").unwrap().unwrap();
+        assert_eq!(Version::Http10, request.version);
+        assert_eq!(None, request.query);
+
+        assert!(parse("").unwrap().is_none());
+        assert!(parse("\r\n").unwrap().is_none());
+    }
+
+    #[test]
+    fn case_parse_body() {
+        let raw = "POST /echo HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\n\r\nhello, extra";
+        let mut reader = raw.as_bytes();
+        let request = Request::read(&mut reader).unwrap().unwrap();
+        assert_eq!(b"hello", &request.body[..]);
+        // the next request starts right after the body
+        assert_eq!(b", extra", reader);
+
+        let raw = "POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n\
+                   5;ext=1\r\nhello\r\n7\r\n, world\r\n0\r\nTrailer: x\r\n\r\nnext";
+        let mut reader = raw.as_bytes();
+        let request = Request::read(&mut reader).unwrap().unwrap();
+        assert_eq!(b"hello, world", &request.body[..]);
+        assert_eq!(b"next", reader);
+    }
+
+    #[test]
+    fn case_keep_alive() {
+        let keep_alive = |raw: &str| parse(raw).unwrap().unwrap().keep_alive();
+        assert!(keep_alive("GET / HTTP/1.1\r\nHost: a\r\n\r\n"));
+        assert!(!keep_alive(
+            "GET / HTTP/1.1\r\nHost: a\r\nConnection: Close\r\n\r\n"
+        ));
+        assert!(!keep_alive(
+            "GET / HTTP/1.1\r\nHost: a\r\nConnection: upgrade, close\r\n\r\n"
+        ));
+        assert!(!keep_alive("GET / HTTP/1.0\r\n\r\n"));
+        assert!(keep_alive(
+            "GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n"
+        ));
+    }
+
+    #[test]
+    fn case_parse_malformed() {
+        let cases = [
+            ("GET /\r\n\r\n", 400),
+            ("GET  / HTTP/1.1\r\nHost: a\r\n\r\n", 400),
+            ("GET index.html HTTP/1.1\r\nHost: a\r\n\r\n", 400),
+            ("G(T / HTTP/1.1\r\nHost: a\r\n\r\n", 400),
+            ("GET / HTTP/2.0\r\n\r\n", 505),
+            ("GET / HTTP/1.1\r\n\r\n", 400),
+            ("GET / HTTP/1.1\r\nHost a\r\n\r\n", 400),
+            ("GET / HTTP/1.1\r\nHost : a\r\n\r\n", 400),
+            ("GET / HTTP/1.1\r\nHost: a\r\n folded\r\n\r\n", 400),
+            ("GET / HTTP/1.1\r\nHost: a\r\n", 400),
+            ("POST / HTTP/1.1\r\nHost: a\r\nContent-Length: -1\r\n\r\n", 400),
+            (
+                "POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\n",
+                400,
+            ),
+            ("POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\n\r\nhi", 400),
+            (
+                "POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 99999999\r\n\r\n",
+                413,
+            ),
+            (
+                "POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: gzip\r\n\r\n",
+                501,
+            ),
+            (
+                "POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\
+                 Transfer-Encoding: gzip\r\n\r\n",
+                501,
+            ),
+            (
+                "POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\
+                 Transfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+                501,
+            ),
+            (
+                "POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: gzip, chunked\r\n\r\n",
+                501,
+            ),
+            (
+                "POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 1\r\nTransfer-Encoding: gzip\r\n\r\n",
+                400,
+            ),
+            (
+                "POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\nContent-Length: 1\r\n\r\n",
+                400,
+            ),
+            (
+                "POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\nz\r\n",
+                400,
+            ),
+            (
+                "POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nabc\r\n0\r\n\r\n",
+                400,
+            ),
+            (
+                "POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n\
+                 5\r\nhello\r\nffffffffffffffff\r\n",
+                413,
+            ),
+            (
+                "POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n\
+                 10000000000000000\r\n",
+                400,
+            ),
+        ];
+        for (raw, status) in cases {
+            let err = parse(raw).unwrap_err();
+            assert_eq!(status, err.status(), "{raw:?}: {err}");
+        }
+
+        let long = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_LINE));
+        assert_eq!(431, parse(&long).unwrap_err().status());
+        let many = format!(
+            "GET / HTTP/1.1\r\n{}\r\n",
+            "A: b\r\n".repeat(MAX_HEADERS + 1)
+        );
+        assert_eq!(431, parse(&many).unwrap_err().status());
+    }
+}
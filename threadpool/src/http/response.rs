@@ -0,0 +1,163 @@
+//! `response` builds an HTTP/1.1 response and writes it out.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+
+#[derive(Debug)]
+pub struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Body,
+    /// false to answer a HEAD request
+    send_body: bool,
+}
+
+#[derive(Debug)]
+enum Body {
+    Bytes(Vec<u8>),
+    /// the next `len` bytes of the file, copied as they are written
+    /// rather than read in memory first
+    File(File, u64),
+}
+
+impl Body {
+    fn len(&self) -> u64 {
+        match self {
+            Body::Bytes(bytes) => bytes.len() as u64,
+            Body::File(_, len) => *len,
+        }
+    }
+}
+
+impl Response {
+    pub fn new(status: u16) -> Response {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: Body::Bytes(Vec::new()),
+            send_body: true,
+        }
+    }
+
+    /// a plain text response saying what went wrong.
+    pub fn error(status: u16, message: &str) -> Response {
+        Response::new(status)
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body(format!("{status} {}: {message}\n", reason(status)))
+    }
+
+    pub fn status(mut self, status: u16) -> Response {
+        self.status = status;
+        self
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Response {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Response {
+        self.body = Body::Bytes(body.into());
+        self
+    }
+
+    /// Sets the body to the `len` bytes of `file` from its current
+    /// position.
+    pub fn file(mut self, file: File, len: u64) -> Response {
+        self.body = Body::File(file, len);
+        self
+    }
+
+    /// Leaves the body out, as the answer to a HEAD request; the
+    /// headers still describe it.
+    pub fn without_body(mut self) -> Response {
+        self.send_body = false;
+        self
+    }
+
+    /// Writes the response, adding `Content-Length` for the body
+    /// unless the status has none.
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason(self.status));
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        if !matches!(self.status, 204 | 304) {
+            head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        head.push_str("\r\n");
+
+        writer.write_all(head.as_bytes())?;
+        match &self.body {
+            _ if !self.send_body => {}
+            Body::Bytes(bytes) => writer.write_all(bytes)?,
+            Body::File(file, len) => {
+                let copied = io::copy(&mut Read::take(file, *len), writer)?;
+                // the file shrank since: the length sent is wrong
+                if copied < *len {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+            }
+        }
+        writer.flush()
+    }
+}
+
+/// the reason phrase of `status`.
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        206 => "Partial Content",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Content Too Large",
+        416 => "Range Not Satisfiable",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn case_write_to() {
+        let mut out = Vec::new();
+        let response = Response::new(200)
+            .header("Content-Type", "text/html")
+            .body("<p>hi</p>");
+        response.write_to(&mut out).unwrap();
+        assert_eq!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 9\r\n\r\n<p>hi</p>",
+            String::from_utf8(out).unwrap()
+        );
+
+        let mut out = Vec::new();
+        Response::error(400, "malformed header")
+            .write_to(&mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(out.ends_with("\r\n\r\n400 Bad Request: malformed header\n"));
+
+        let mut out = Vec::new();
+        Response::new(200)
+            .body("<p>hi</p>")
+            .without_body()
+            .write_to(&mut out)
+            .unwrap();
+        assert_eq!(
+            "HTTP/1.1 200 OK\r\nContent-Length: 9\r\n\r\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
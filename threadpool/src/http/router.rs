@@ -0,0 +1,230 @@
+//! `router` picks the handler of a request by its method and path.
+//!
+//! A pattern is matched segment by segment:
+//!
+//! - `users` matches that segment only
+//! - `:id` matches any one segment, and binds it to `id`
+//! - `*rest`, last, matches whatever is left, even nothing, and
+//!   binds it to `rest`; a bare `*` binds nothing
+//!
+//! Routes are tried in the order they were added, so `/users/me`
+//! must come before `/users/:id` to be matched at all.
+//!
+//! A `GET` route also takes `HEAD` requests, whose response is then
+//! sent without its body.
+
+#[derive(Debug)]
+pub struct Router<H> {
+    routes: Vec<Route<H>>,
+}
+
+#[derive(Debug)]
+struct Route<H> {
+    method: String,
+    segments: Vec<Segment>,
+    handler: H,
+}
+
+#[derive(Debug)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    Rest(Option<String>),
+}
+
+/// the segments bound by the parameters of a pattern.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Params(Vec<(String, String)>);
+
+impl Params {
+    /// returns the segment bound to `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        let (_, value) = self.0.iter().find(|(param, _)| param == name)?;
+        Some(value)
+    }
+}
+
+/// what the router found for a request.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Match<'a, H> {
+    Found(&'a H, Params),
+    /// some route has the path, but none the method; holds the
+    /// methods which have it, for the `Allow` header
+    MethodNotAllowed(Vec<&'a str>),
+    NotFound,
+}
+
+impl<H> Router<H> {
+    pub fn new() -> Router<H> {
+        Router { routes: Vec::new() }
+    }
+
+    /// Adds a route for `method` on the paths matching `pattern`.
+    ///
+    /// # Panics
+    ///
+    /// It panics if `pattern` does not start with `/`, or has a `*`
+    /// segment other than the last.
+    pub fn route(mut self, method: &str, pattern: &str, handler: H) -> Router<H> {
+        let segments = parse(pattern);
+        self.routes.push(Route {
+            method: method.to_string(),
+            segments,
+            handler,
+        });
+        self
+    }
+
+    /// finds the route for `method` on `path`.
+    pub fn find(&self, method: &str, path: &str) -> Match<'_, H> {
+        let mut allow = Vec::new();
+        for route in &self.routes {
+            let Some(params) = matches(&route.segments, path) else {
+                continue;
+            };
+            if route.method == method || (method == "HEAD" && route.method == "GET") {
+                return Match::Found(&route.handler, params);
+            }
+            if !allow.contains(&route.method.as_str()) {
+                allow.push(route.method.as_str());
+            }
+        }
+        if allow.is_empty() {
+            Match::NotFound
+        } else {
+            Match::MethodNotAllowed(allow)
+        }
+    }
+}
+
+impl<H> Default for Router<H> {
+    fn default() -> Router<H> {
+        Router::new()
+    }
+}
+
+fn parse(pattern: &str) -> Vec<Segment> {
+    let Some(pattern) = pattern.strip_prefix('/') else {
+        panic!("pattern {pattern:?} does not start with '/'");
+    };
+    let segments: Vec<_> = pattern
+        .split('/')
+        .map(|segment| {
+            if let Some(name) = segment.strip_prefix(':') {
+                Segment::Param(name.to_string())
+            } else if let Some(name) = segment.strip_prefix('*') {
+                Segment::Rest((!name.is_empty()).then(|| name.to_string()))
+            } else {
+                Segment::Literal(segment.to_string())
+            }
+        })
+        .collect();
+
+    let last = segments.len() - 1;
+    let misplaced = segments[..last]
+        .iter()
+        .any(|segment| matches!(segment, Segment::Rest(_)));
+    assert!(!misplaced, "pattern {pattern:?} has '*' before the end");
+    segments
+}
+
+/// returns the parameters if `path` matches `segments`.
+fn matches(segments: &[Segment], path: &str) -> Option<Params> {
+    let parts: Vec<&str> = path.strip_prefix('/')?.split('/').collect();
+    let mut params = Vec::new();
+    for (i, segment) in segments.iter().enumerate() {
+        if let Segment::Rest(name) = segment {
+            if let Some(name) = name {
+                params.push((name.clone(), parts[i..].join("/")));
+            }
+            return Some(Params(params));
+        }
+        let part = parts.get(i)?;
+        match segment {
+            Segment::Literal(literal) if literal == part => {}
+            Segment::Param(name) if !part.is_empty() => {
+                params.push((name.clone(), part.to_string()));
+            }
+            _ => return None,
+        }
+    }
+    (parts.len() == segments.len()).then_some(Params(params))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> Params {
+        Params(
+            pairs
+                .iter()
+                .map(|&(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn case_find() {
+        let router = Router::new()
+            .route("GET", "/", 0)
+            .route("GET", "/users/me", 1)
+            .route("GET", "/users/:id", 2)
+            .route("DELETE", "/users/:id", 3)
+            .route("GET", "/users/:id/posts/:post", 4)
+            .route("GET", "/static/*path", 5)
+            .route("GET", "/any/*", 6);
+
+        assert_eq!(Match::Found(&0, params(&[])), router.find("GET", "/"));
+        assert_eq!(
+            Match::Found(&1, params(&[])),
+            router.find("GET", "/users/me")
+        );
+        assert_eq!(
+            Match::Found(&2, params(&[("id", "42")])),
+            router.find("GET", "/users/42")
+        );
+        assert_eq!(
+            Match::Found(&3, params(&[("id", "42")])),
+            router.find("DELETE", "/users/42")
+        );
+        let found = router.find("GET", "/users/7/posts/9");
+        let Match::Found(&4, bound) = found else {
+            panic!("{found:?}");
+        };
+        assert_eq!((Some("7"), Some("9")), (bound.get("id"), bound.get("post")));
+
+        let found = router.find("GET", "/static/css/site.css");
+        assert_eq!(Match::Found(&5, params(&[("path", "css/site.css")])), found);
+        let found = router.find("GET", "/static");
+        assert_eq!(Match::Found(&5, params(&[("path", "")])), found);
+        assert_eq!(
+            Match::Found(&6, params(&[])),
+            router.find("GET", "/any/a/b")
+        );
+
+        assert_eq!(
+            Match::Found(&5, params(&[("path", "a.css")])),
+            router.find("HEAD", "/static/a.css")
+        );
+
+        assert_eq!(Match::NotFound, router.find("GET", "/users"));
+        assert_eq!(Match::NotFound, router.find("GET", "/users/"));
+        assert_eq!(Match::NotFound, router.find("GET", "/users/1/posts"));
+        assert_eq!(Match::NotFound, router.find("GET", "/nope"));
+        assert_eq!(
+            Match::MethodNotAllowed(vec!["GET", "DELETE"]),
+            router.find("PUT", "/users/42")
+        );
+        assert_eq!(
+            Match::MethodNotAllowed(vec!["GET"]),
+            router.find("POST", "/")
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "has '*' before the end")]
+    fn case_route_misplaced_rest() {
+        let _ = Router::new().route("GET", "/*rest/more", ());
+    }
+}
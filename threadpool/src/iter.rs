@@ -0,0 +1,169 @@
+//! `iter` spreads the items of a collection over the workers.
+//!
+//! The items are split into a few chunks per worker, so that a job
+//! handles many items and a slow chunk does not leave the other
+//! workers idle for long. The jobs are scoped, see
+//! [`ThreadPool::scope`], so `f` may borrow local data.
+
+use crate::{JoinError, ThreadPool};
+use std::panic;
+
+/// how many chunks each worker gets, to even out uneven items.
+const CHUNKS_PER_WORKER: usize = 4;
+
+impl ThreadPool {
+    /// Applies `f` to every item on the workers, and returns the
+    /// results in the order of the items.
+    ///
+    /// If `f` panics, the panic is resumed on the calling thread
+    /// once all the chunks are done.
+    ///
+    /// ```rust
+    /// let pool = threadpool::ThreadPool::new(4);
+    /// let offset = 1;
+    /// let squares = pool.map(1..=5, |x| x * x + offset);
+    /// assert_eq!(vec![2, 5, 10, 17, 26], squares);
+    /// ```
+    pub fn map<I, F, R>(&self, items: I, f: F) -> Vec<R>
+    where
+        I: IntoIterator,
+        I::Item: Send,
+        F: Fn(I::Item) -> R + Sync,
+        R: Send,
+    {
+        let items: Vec<_> = items.into_iter().collect();
+        let len = items.len();
+        let chunks = self.split(items);
+        let f = &f;
+
+        let results = self.scope(|s| {
+            let handles: Vec<_> = chunks
+                .into_iter()
+                .map(|chunk| s.spawn(move || chunk.into_iter().map(f).collect::<Vec<_>>()))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join())
+                .collect::<Vec<_>>()
+        });
+
+        let mut mapped = Vec::with_capacity(len);
+        for result in results {
+            mapped.extend(result.unwrap_or_else(|e| resume(e)));
+        }
+        mapped
+    }
+
+    /// Calls `f` with every item on the workers, in chunks, and
+    /// returns once all of them are done.
+    ///
+    /// If `f` panics, the panic is resumed on the calling thread
+    /// once all the chunks are done.
+    ///
+    /// ```rust
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// let pool = threadpool::ThreadPool::new(4);
+    /// let total = AtomicUsize::new(0);
+    /// pool.for_each(1..=100, |x| {
+    ///     total.fetch_add(x, Ordering::Relaxed);
+    /// });
+    /// assert_eq!(5050, total.into_inner());
+    /// ```
+    pub fn for_each<I, F>(&self, items: I, f: F)
+    where
+        I: IntoIterator,
+        I::Item: Send,
+        F: Fn(I::Item) + Sync,
+    {
+        let chunks = self.split(items.into_iter().collect());
+        let f = &f;
+
+        let results = self.scope(|s| {
+            let handles: Vec<_> = chunks
+                .into_iter()
+                .map(|chunk| s.spawn(move || chunk.into_iter().for_each(f)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join())
+                .collect::<Vec<_>>()
+        });
+
+        for result in results {
+            result.unwrap_or_else(|e| resume(e));
+        }
+    }
+
+    /// splits `items` into chunks of about the same size.
+    fn split<T>(&self, items: Vec<T>) -> Vec<Vec<T>> {
+        let workers = self.stats().workers.max(1);
+        let size = items.len().div_ceil(workers * CHUNKS_PER_WORKER).max(1);
+
+        let mut items = items.into_iter();
+        let mut chunks = Vec::new();
+        loop {
+            let chunk: Vec<T> = items.by_ref().take(size).collect();
+            if chunk.is_empty() {
+                return chunks;
+            }
+            chunks.push(chunk);
+        }
+    }
+}
+
+/// resumes the panic of a chunk on the calling thread.
+fn resume(e: JoinError) -> ! {
+    match e.try_into_panic() {
+        Ok(payload) => panic::resume_unwind(payload),
+        Err(e) => panic!("{e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::AssertUnwindSafe;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn case_map() {
+        let pool = ThreadPool::new(3);
+
+        let items: Vec<usize> = (0..1000).collect();
+        let doubled = pool.map(&items, |x| x * 2);
+        assert_eq!((0..1000).map(|x| x * 2).collect::<Vec<_>>(), doubled);
+
+        let empty: Vec<usize> = pool.map(Vec::<usize>::new(), |x| x);
+        assert!(empty.is_empty());
+
+        let chunks = pool.split((0..10).collect());
+        assert_eq!(vec![vec![0], vec![1]], chunks[..2]);
+        assert_eq!(10, chunks.len());
+    }
+
+    #[test]
+    fn case_for_each() {
+        let pool = ThreadPool::new(2);
+        let seen = AtomicUsize::new(0);
+        pool.for_each(0..100, |_| {
+            seen.fetch_add(1, Ordering::Relaxed);
+        });
+        assert_eq!(100, seen.load(Ordering::Relaxed));
+
+        // a panic comes back once every chunk is done
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            pool.for_each(0..100, |x| {
+                if x == 50 {
+                    panic!("fifty");
+                }
+                seen.fetch_add(1, Ordering::Relaxed);
+            })
+        }));
+        let payload = result.unwrap_err();
+        assert_eq!(Some(&"fifty"), payload.downcast_ref::<&str>());
+        // 100 items in 8 chunks of 13: all ran but 50 and 51, the
+        // rest of its chunk
+        assert_eq!(100 + 98, seen.load(Ordering::Relaxed));
+    }
+}
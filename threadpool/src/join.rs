@@ -0,0 +1,289 @@
+//! `join` carries the result of a job back to the caller.
+//!
+//! [`ThreadPool::spawn`](crate::ThreadPool::spawn) wraps the user's
+//! closure so that its return value, or its panic, is stored in a
+//! slot shared with the returned [`JoinHandle`]. The handle may be
+//! waited for with [`join`](JoinHandle::join), or awaited as a
+//! [`Future`] from async code.
+
+use crate::Outcome;
+use std::any::Any;
+use std::fmt;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+/// the slot shared by a job and its handle.
+struct Packet<T> {
+    slot: Mutex<Slot<T>>,
+    done: Condvar,
+}
+
+struct Slot<T> {
+    result: Option<Result<T, JoinError>>,
+    /// the task awaiting the handle, if any
+    waker: Option<Waker>,
+}
+
+impl<T> Packet<T> {
+    fn lock(&self) -> MutexGuard<'_, Slot<T>> {
+        self.slot.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// creates the two ends of a job's result slot.
+pub(crate) fn pair<T>() -> (JoinHandle<T>, Completer<T>) {
+    let packet = Arc::new(Packet {
+        slot: Mutex::new(Slot {
+            result: None,
+            waker: None,
+        }),
+        done: Condvar::new(),
+    });
+    (
+        JoinHandle {
+            packet: Arc::clone(&packet),
+        },
+        Completer {
+            packet: Some(packet),
+        },
+    )
+}
+
+/// the job's end of the slot.
+///
+/// If it is dropped before the job runs, e.g. because the pool
+/// is gone, the handle sees a cancelled job instead of waiting
+/// forever.
+pub(crate) struct Completer<T> {
+    packet: Option<Arc<Packet<T>>>,
+}
+
+impl<T> Completer<T> {
+    /// runs `f`, catching a panic, and stores the outcome.
+    pub(crate) fn run<F>(self, f: F) -> Outcome
+    where
+        F: FnOnce() -> T,
+    {
+        self.finish(panic::catch_unwind(AssertUnwindSafe(f)))
+    }
+
+    /// stores the return value or the panic of a job.
+    pub(crate) fn finish(mut self, result: thread::Result<T>) -> Outcome {
+        let result = result.map_err(JoinError::panic);
+        let outcome = match result {
+            Ok(_) => Outcome::Completed,
+            Err(_) => Outcome::Panicked(None),
+        };
+        self.complete(result);
+        outcome
+    }
+
+    fn complete(&mut self, result: Result<T, JoinError>) {
+        if let Some(packet) = self.packet.take() {
+            let mut slot = packet.lock();
+            slot.result = Some(result);
+            let waker = slot.waker.take();
+            drop(slot);
+            packet.done.notify_all();
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    fn drop(&mut self) {
+        self.complete(Err(JoinError::cancelled()));
+    }
+}
+
+/// an owned permission to wait for a spawned job.
+///
+/// Dropping the handle detaches the job: it still runs, but its
+/// result is thrown away.
+pub struct JoinHandle<T> {
+    packet: Arc<Packet<T>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Waits for the job to finish and returns its result.
+    ///
+    /// If the job panicked, or was dropped by the pool without
+    /// running, an error is returned instead.
+    pub fn join(self) -> Result<T, JoinError> {
+        let mut slot = self.packet.lock();
+        loop {
+            if let Some(result) = slot.result.take() {
+                return result;
+            }
+            slot = self
+                .packet
+                .done
+                .wait(slot)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Checks whether the job has finished, without blocking.
+    ///
+    /// Once it returns `true`, [`join`](JoinHandle::join) returns
+    /// immediately.
+    pub fn is_finished(&self) -> bool {
+        self.packet.lock().result.is_some()
+    }
+}
+
+/// Awaiting the handle does not block the thread, so async code can
+/// hand blocking work to the pool:
+///
+/// ```rust
+/// let pool = threadpool::ThreadPool::new(2);
+///
+/// let blocking = pool.spawn(|| std::fs::read_to_string("Cargo.toml"));
+/// let task = pool.spawn_future(async move {
+///     let manifest = blocking.await.unwrap().unwrap();
+///     manifest.lines().count()
+/// });
+/// assert!(task.join().unwrap() > 0);
+/// ```
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.packet.lock();
+        match slot.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> fmt::Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JoinHandle")
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
+
+/// why a job did not produce a result.
+pub struct JoinError {
+    repr: Repr,
+}
+
+enum Repr {
+    Panic(Box<dyn Any + Send + 'static>),
+    Cancelled,
+}
+
+impl JoinError {
+    fn panic(payload: Box<dyn Any + Send + 'static>) -> JoinError {
+        JoinError {
+            repr: Repr::Panic(payload),
+        }
+    }
+
+    fn cancelled() -> JoinError {
+        JoinError {
+            repr: Repr::Cancelled,
+        }
+    }
+
+    /// returns true if the job panicked.
+    pub fn is_panic(&self) -> bool {
+        matches!(self.repr, Repr::Panic(_))
+    }
+
+    /// returns true if the job was dropped without running.
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self.repr, Repr::Cancelled)
+    }
+
+    /// Returns the panic payload, e.g. to resume the panic with
+    /// [`std::panic::resume_unwind`], or the error itself if the
+    /// job did not panic.
+    pub fn try_into_panic(self) -> Result<Box<dyn Any + Send + 'static>, JoinError> {
+        match self.repr {
+            Repr::Panic(payload) => Ok(payload),
+            Repr::Cancelled => Err(self),
+        }
+    }
+
+    fn message(&self) -> Option<&str> {
+        match &self.repr {
+            Repr::Panic(payload) => payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str)),
+            Repr::Cancelled => None,
+        }
+    }
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.repr, self.message()) {
+            (Repr::Panic(_), Some(msg)) => write!(f, "job panicked: {msg}"),
+            (Repr::Panic(_), None) => write!(f, "job panicked"),
+            (Repr::Cancelled, _) => write!(f, "job was cancelled"),
+        }
+    }
+}
+
+impl fmt::Debug for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.repr {
+            Repr::Panic(_) => f.debug_tuple("Panic").field(&self.message()).finish(),
+            Repr::Cancelled => f.write_str("Cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn case_join() {
+        let (handle, completer) = pair();
+        assert!(!handle.is_finished());
+        thread::spawn(move || {
+            completer.run(|| 6 * 7);
+        });
+        assert_eq!(42, handle.join().unwrap());
+    }
+
+    #[test]
+    fn case_join_panic() {
+        let (handle, completer) = pair::<()>();
+        completer.run(|| panic!("boom"));
+        assert!(handle.is_finished());
+
+        let err = handle.join().unwrap_err();
+        assert!(err.is_panic());
+        assert_eq!("job panicked: boom", err.to_string());
+        let payload = err.try_into_panic().unwrap();
+        assert_eq!(Some(&"boom"), payload.downcast_ref::<&str>());
+    }
+
+    #[test]
+    fn case_join_cancelled() {
+        let (handle, completer) = pair::<()>();
+        drop(completer);
+
+        let err = handle.join().unwrap_err();
+        assert!(err.is_cancelled());
+        assert!(err.try_into_panic().is_err());
+    }
+}
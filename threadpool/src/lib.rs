@@ -1,77 +1,906 @@
-use std::sync::{mpsc, Arc, Mutex};
-use std::thread;
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+mod affinity;
+mod builder;
+mod future;
+mod iter;
+mod join;
+mod listener;
+mod queue;
+mod scope;
+mod timer;
+mod worker;
+
+use builder::ThreadConfig;
+pub use builder::{Builder, PoolCreationError};
+pub use join::{JoinError, JoinHandle};
+use listener::Counters;
+pub use listener::{Event, Listener, Stats};
+pub use queue::Priority;
+use queue::{Queue, Wait};
+pub use scope::Scope;
+use timer::Timer;
+pub use timer::TimerHandle;
+use worker::Workers;
 
 pub struct ThreadPool {
-    workers: Vec<Worker>,
-    sender: Option<mpsc::Sender<Job>>,
+    shared: Arc<Shared>,
 }
 
-type Job = Box<dyn FnOnce() + Send + 'static>;
+type Job = Box<dyn FnOnce() -> Outcome + Send + 'static>;
 
-impl ThreadPool {
-    pub fn new(size: usize) -> ThreadPool {
-        let (tx, rx) = mpsc::channel();
-        let rx = Arc::new(Mutex::new(rx));
+/// how a job ended, as seen by the worker running it.
+enum Outcome {
+    Completed,
+    /// the payload is `None` if the job already handed it to a
+    /// [`JoinHandle`].
+    Panicked(Option<Box<dyn Any + Send>>),
+    /// the job polled a future which is not ready yet; it counts
+    /// once a later poll completes it
+    Pending,
+}
+
+/// boxes a closure given to [`ThreadPool::execute`] as a job.
+fn into_job<F>(f: F) -> Job
+where
+    F: FnOnce() + Send + 'static,
+{
+    Box::new(move || {
+        f();
+        Outcome::Completed
+    })
+}
+
+/// Called with the worker's id and the panic payload when a job
+/// run by [`ThreadPool::execute`] panics.
+pub type PanicHandler = dyn Fn(usize, Box<dyn Any + Send>) + Send + Sync + 'static;
+
+/// the state shared by the pool and its workers.
+struct Shared {
+    queue: Queue,
+    timer: Timer,
+    workers: Mutex<Workers>,
+    /// signalled, with `workers` locked, when a worker is done
+    exited: Condvar,
+    /// copies of the sizes in `workers`, read without its lock
+    alive: AtomicUsize,
+    core: AtomicUsize,
+    max: AtomicUsize,
+    /// how long a worker beyond the core size may stay idle
+    keep_alive: Duration,
+    panic_handler: Mutex<Option<Arc<PanicHandler>>>,
+    thread: ThreadConfig,
+    listener: Option<Arc<dyn Listener>>,
+    counters: Counters,
+}
+
+impl Shared {
+    fn emit(&self, event: Event) {
+        if let Some(listener) = &self.listener {
+            listener.on_event(&event);
+        }
+    }
+
+    fn send<T>(
+        self: &Arc<Self>,
+        item: T,
+        into_job: impl FnOnce(T) -> Job,
+        wait: Wait,
+        priority: Priority,
+    ) -> Result<(), T> {
+        self.queue.push(item, into_job, wait, priority)?;
+        self.grow();
+        Ok(())
+    }
+
+    /// adds a worker if jobs are waiting with no worker idle, and
+    /// the pool is below its maximum size.
+    fn grow(self: &Arc<Self>) {
+        if self.alive.load(Ordering::SeqCst) >= self.max.load(Ordering::SeqCst)
+            || self.queue.len() <= self.queue.sleepers()
+        {
+            return;
+        }
+
+        let mut workers = lock(&self.workers);
+        if workers.alive() >= workers.max {
+            return;
+        }
+        match workers.spawn(self) {
+            Ok(worker) => self.emit(Event::WorkerSpawned { worker }),
+            Err(error) => self.emit(Event::WorkerSpawnFailed { error: &error }),
+        }
+    }
+}
 
-        let mut workers = Vec::with_capacity(size);
+/// locks `mutex` even if a thread panicked while holding it: no
+/// state guarded by the pool's mutexes is left half updated.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
 
-        for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&rx)));
+impl ThreadPool {
+    /// Creates a pool of `size` workers.
+    ///
+    /// # Panics
+    ///
+    /// It panics if `size` is zero or a worker thread cannot be
+    /// spawned. Use [`ThreadPool::builder`] to handle those errors.
+    pub fn new(size: usize) -> ThreadPool {
+        match ThreadPool::builder().size(size).build() {
+            Ok(pool) => pool,
+            Err(e) => panic!("{e}"),
         }
+    }
 
-        ThreadPool {
-            workers,
-            sender: Some(tx),
+    /// Creates a pool with a worker per CPU, see
+    /// [`Builder::available_parallelism`].
+    ///
+    /// # Panics
+    ///
+    /// It panics if a worker thread cannot be spawned.
+    pub fn with_available_parallelism() -> ThreadPool {
+        match ThreadPool::builder().available_parallelism().build() {
+            Ok(pool) => pool,
+            Err(e) => panic!("{e}"),
         }
     }
 
+    /// Returns a [`Builder`] to configure the pool, e.g. the names
+    /// and stack size of its threads.
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+
+    /// Sets the handler called when a job panics.
+    ///
+    /// The panic is caught, so the worker goes on with the next
+    /// job either way. Should the handler itself panic, the worker
+    /// thread dies and a new one takes its place.
+    ///
+    /// ```rust
+    /// use std::sync::mpsc;
+    ///
+    /// let pool = threadpool::ThreadPool::new(1);
+    /// let (tx, rx) = mpsc::channel();
+    /// let tx = std::sync::Mutex::new(tx);
+    /// pool.set_panic_handler(move |_id, payload| {
+    ///     let msg = payload.downcast_ref::<&str>().unwrap().to_string();
+    ///     tx.lock().unwrap().send(msg).unwrap();
+    /// });
+    ///
+    /// pool.execute(|| panic!("boom"));
+    /// assert_eq!("boom", rx.recv().unwrap());
+    /// ```
+    pub fn set_panic_handler<F>(&self, handler: F)
+    where
+        F: Fn(usize, Box<dyn Any + Send>) + Send + Sync + 'static,
+    {
+        *lock(&self.shared.panic_handler) = Some(Arc::new(handler));
+    }
+
+    /// Queues `f` to run on one of the workers.
+    ///
+    /// If the pool was built with a
+    /// [`queue_capacity`](Builder::queue_capacity) and the queue is
+    /// full, it blocks until a worker takes a job.
     pub fn execute<F>(&self, f: F)
     where
         F: FnOnce() + Send + 'static,
     {
-        if let Some(tx) = &self.sender {
-            let _ = tx.send(Box::new(f));
+        let _ = self.push(f, Wait::Forever, Priority::Normal);
+    }
+
+    /// Queues `f` like [`execute`](ThreadPool::execute), ahead of or
+    /// behind the other queued jobs depending on `priority`.
+    ///
+    /// ```rust
+    /// use threadpool::Priority;
+    ///
+    /// let pool = threadpool::ThreadPool::new(4);
+    /// pool.execute_priority(|| println!("answer a user"), Priority::High);
+    /// pool.execute_priority(|| println!("rebuild an index"), Priority::Low);
+    /// ```
+    pub fn execute_priority<F>(&self, f: F, priority: Priority)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let _ = self.push(f, Wait::Forever, priority);
+    }
+
+    /// Queues `f` like [`execute`](ThreadPool::execute), but hands
+    /// it back instead of blocking if the queue is full.
+    ///
+    /// ```rust
+    /// use std::sync::mpsc;
+    ///
+    /// let pool = threadpool::ThreadPool::builder()
+    ///     .size(1)
+    ///     .queue_capacity(1)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let (tx, rx) = mpsc::channel::<()>();
+    /// let busy = pool.spawn(move || rx.recv().unwrap());
+    /// while pool.stats().running == 0 {}
+    ///
+    /// assert!(pool.try_execute(|| ()).is_ok());
+    /// let job = pool.try_execute(|| ()).unwrap_err().into_job();
+    ///
+    /// tx.send(()).unwrap();
+    /// busy.join().unwrap();
+    /// pool.execute(job);
+    /// ```
+    pub fn try_execute<F>(&self, f: F) -> Result<(), QueueFullError<F>>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.push(f, Wait::No, Priority::Normal)
+            .map_err(QueueFullError)
+    }
+
+    /// Queues `f` like [`execute`](ThreadPool::execute), but hands
+    /// it back if the queue is still full after `timeout`.
+    pub fn execute_timeout<F>(&self, f: F, timeout: Duration) -> Result<(), QueueFullError<F>>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let wait = match Instant::now().checked_add(timeout) {
+            Some(deadline) => Wait::Until(deadline),
+            None => Wait::Forever,
+        };
+        self.push(f, wait, Priority::Normal).map_err(QueueFullError)
+    }
+
+    fn push<F>(&self, f: F, wait: Wait, priority: Priority) -> Result<(), F>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.shared.send(f, into_job, wait, priority)
+    }
+
+    /// Changes the core size of the pool.
+    ///
+    /// Growing starts the new workers at once. Shrinking lets the
+    /// extra workers finish their jobs; they retire once they have
+    /// been idle for the [`keep_alive`](Builder::keep_alive) time.
+    /// The maximum size is raised to `size` if it is lower, and a
+    /// pool built without room to grow stays at exactly `size`.
+    ///
+    /// ```rust
+    /// let pool = threadpool::ThreadPool::new(1);
+    /// pool.set_size(3).unwrap();
+    /// assert_eq!(3, pool.stats().workers);
+    /// ```
+    pub fn set_size(&self, size: usize) -> Result<(), PoolCreationError> {
+        if size == 0 {
+            return Err(PoolCreationError::ZeroSize);
+        }
+
+        let shared = &self.shared;
+        let mut workers = lock(&shared.workers);
+        if workers.max == workers.core || workers.max < size {
+            workers.max = size;
+        }
+        workers.core = size;
+        workers.sync(shared);
+        while workers.alive() < size {
+            let worker = workers.spawn(shared).map_err(PoolCreationError::Spawn)?;
+            shared.emit(Event::WorkerSpawned { worker });
+        }
+        drop(workers);
+
+        // sleeping workers check again whether they are extra
+        shared.queue.wake_all();
+        Ok(())
+    }
+
+    /// Returns the pool's counters at this moment.
+    ///
+    /// A worker updates them right after a job returns, so they
+    /// may lag a moment behind a [`JoinHandle`] of that job.
+    ///
+    /// ```rust
+    /// let pool = threadpool::ThreadPool::new(2);
+    /// pool.execute(|| ());
+    /// pool.execute(|| panic!());
+    ///
+    /// while pool.stats().completed + pool.stats().panicked < 2 {
+    ///     std::thread::yield_now();
+    /// }
+    /// let stats = pool.stats();
+    /// assert_eq!((1, 1), (stats.completed, stats.panicked));
+    /// assert_eq!((0, 0), (stats.queued, stats.running));
+    /// ```
+    pub fn stats(&self) -> Stats {
+        let shared = &self.shared;
+        let workers = shared.alive.load(Ordering::SeqCst);
+        shared.counters.snapshot(workers, shared.queue.len())
+    }
+
+    /// Runs `f` on the pool like [`execute`](ThreadPool::execute),
+    /// and returns a handle to wait for its return value.
+    ///
+    /// A panic in `f` does not reach the worker; `join()` returns
+    /// it as an error instead.
+    ///
+    /// ```rust
+    /// let pool = threadpool::ThreadPool::new(2);
+    ///
+    /// let handle = pool.spawn(|| 6 * 7);
+    /// assert_eq!(42, handle.join().unwrap());
+    ///
+    /// let handle = pool.spawn(|| panic!("boom"));
+    /// assert!(handle.join().unwrap_err().is_panic());
+    /// ```
+    pub fn spawn<F, T>(&self, f: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.spawn_priority(f, Priority::Normal)
+    }
+
+    /// Runs `f` like [`spawn`](ThreadPool::spawn), with the given
+    /// `priority` as in [`execute_priority`](ThreadPool::execute_priority).
+    pub fn spawn_priority<F, T>(&self, f: F, priority: Priority) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (handle, completer) = join::pair();
+        let _ = self.shared.send(
+            f,
+            move |f| Box::new(move || completer.run(f)),
+            Wait::Forever,
+            priority,
+        );
+        handle
+    }
+
+    /// Stops taking jobs, waits for the queued ones to run, then
+    /// for the workers to exit. Dropping the pool does the same.
+    ///
+    /// Called from one of the pool's own jobs, it does not wait for
+    /// the worker running that job, which could never exit first: the
+    /// worker is detached and exits once the job returns.
+    pub fn shutdown(self) {
+        self.close();
+        self.join(None);
+    }
+
+    /// Stops taking jobs and drops the ones still queued, then
+    /// waits for the running ones. Returns how many were dropped;
+    /// their [`JoinHandle`]s see them cancelled.
+    ///
+    /// ```rust
+    /// use std::{thread, time::Duration};
+    ///
+    /// let pool = threadpool::ThreadPool::new(1);
+    /// let running = pool.spawn(|| thread::sleep(Duration::from_millis(100)));
+    /// while pool.stats().running == 0 {}
+    /// let queued = pool.spawn(|| ());
+    ///
+    /// assert_eq!(1, pool.shutdown_now());
+    /// assert!(running.join().is_ok());
+    /// assert!(queued.join().unwrap_err().is_cancelled());
+    /// ```
+    pub fn shutdown_now(self) -> usize {
+        self.close();
+        let cancelled = self.shared.queue.clear();
+        self.join(None);
+        cancelled
+    }
+
+    /// Shuts down like [`shutdown`](ThreadPool::shutdown), but waits
+    /// for at most `timeout`.
+    ///
+    /// If the workers are not done by then, the jobs still queued
+    /// are dropped and the workers left running their current job
+    /// are detached: they exit once it returns. The error tells
+    /// which workers they are.
+    pub fn shutdown_timeout(self, timeout: Duration) -> Result<(), ShutdownTimeoutError> {
+        self.close();
+        let deadline = Instant::now().checked_add(timeout);
+        if self.join(deadline) {
+            return Ok(());
+        }
+        // the queued jobs go first, or the detached workers would
+        // still run them after this returns.
+        let cancelled = self.shared.queue.clear();
+        let mut workers = lock(&self.shared.workers);
+        let me = workers.current();
+        let workers = workers
+            .detach(&self.shared)
+            .into_iter()
+            .filter(|&id| Some(id) != me)
+            .collect();
+        Err(ShutdownTimeoutError { workers, cancelled })
+    }
+
+    fn close(&self) {
+        self.shared.emit(Event::ShutdownStarted);
+        // the timer may still queue a due job until it is closed.
+        // Closing the queue then turns away a job it is blocked on,
+        // if the queue is full, so that it can stop.
+        self.shared.timer.close();
+        self.shared.queue.close();
+        self.shared.timer.join();
+    }
+
+    /// joins the workers as they exit, but for the calling one if
+    /// called from a job, which is detached instead. Returns whether
+    /// they all were before the deadline.
+    fn join(&self, deadline: Option<Instant>) -> bool {
+        let shared = &self.shared;
+        let mut workers = lock(&shared.workers);
+        let me = workers.current();
+        loop {
+            // a worker dying now is replaced in its slot, so look for
+            // workers until none is left.
+            if let Some(thread) = workers.take_done(shared) {
+                drop(workers);
+                let _ = thread.join();
+                workers = lock(&shared.workers);
+                continue;
+            }
+            if workers.alive() == usize::from(me.is_some()) {
+                workers.detach(shared);
+                return true;
+            }
+
+            workers = match deadline {
+                None => shared
+                    .exited
+                    .wait(workers)
+                    .unwrap_or_else(PoisonError::into_inner),
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(left) if !left.is_zero() => {
+                        let result = shared.exited.wait_timeout(workers, left);
+                        result.unwrap_or_else(PoisonError::into_inner).0
+                    }
+                    _ => return false,
+                },
+            };
         }
     }
 }
 
 impl Drop for ThreadPool {
+    /// Shuts the pool down like [`shutdown`](ThreadPool::shutdown),
+    /// unless it already was. Dropped from one of its own jobs, the
+    /// pool does not wait for the worker running it.
     fn drop(&mut self) {
-        println!("Dropping ThreadPool!");
-        drop(self.sender.take());
-        for worker in &mut self.workers {
-            if let Some(thread) = worker.thread.take() {
-                let _ = thread.join();
-                println!("worker {} exit.", worker.id);
-            }
+        if !self.shared.queue.is_closed() {
+            self.close();
+            self.join(None);
         }
     }
 }
 
-struct Worker {
-    id: usize,
-    thread: Option<thread::JoinHandle<()>>,
+/// returned when a job does not fit in the pool's bounded queue.
+///
+/// It holds the job so that the caller may retry, run it itself,
+/// or drop it.
+pub struct QueueFullError<F>(F);
+
+impl<F> QueueFullError<F> {
+    /// returns the job which was not queued.
+    pub fn into_job(self) -> F {
+        self.0
+    }
+}
+
+impl<F> fmt::Debug for QueueFullError<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("QueueFullError(..)")
+    }
+}
+
+impl<F> fmt::Display for QueueFullError<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the pool's queue is full")
+    }
+}
+
+impl<F> Error for QueueFullError<F> {}
+
+/// returned when [`ThreadPool::shutdown_timeout`] runs out of time.
+#[derive(Debug)]
+pub struct ShutdownTimeoutError {
+    workers: Vec<usize>,
+    cancelled: usize,
+}
+
+impl ShutdownTimeoutError {
+    /// the ids of the workers which were still running a job.
+    pub fn workers(&self) -> &[usize] {
+        &self.workers
+    }
+
+    /// how many queued jobs were dropped without running.
+    pub fn cancelled(&self) -> usize {
+        self.cancelled
+    }
 }
 
-impl Worker {
-    fn new(id: usize, rx: Arc<Mutex<mpsc::Receiver<Job>>>) -> Worker {
-        let handler = thread::spawn(move || loop {
-            let job = rx.lock().unwrap().recv();
-            match job {
-                Ok(job) => {
-                    println!("worker {id} gets a job. Executing..");
-                    job();
-                    println!("worker {id} gets the job done.");
+impl fmt::Display for ShutdownTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} workers did not finish in time, {} queued jobs were dropped",
+            self.workers.len(),
+            self.cancelled
+        )
+    }
+}
+
+impl Error for ShutdownTimeoutError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[test]
+    fn case_spawn() {
+        let pool = ThreadPool::new(2);
+
+        let handles: Vec<_> = (0..8).map(|i| pool.spawn(move || i * i)).collect();
+        let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(vec![0, 1, 4, 9, 16, 25, 36, 49], results);
+
+        // a panicking job leaves the worker alive
+        let pool = ThreadPool::new(1);
+        let err = pool.spawn(|| panic!("boom")).join().unwrap_err();
+        assert!(err.is_panic());
+        assert_eq!("ok", pool.spawn(|| "ok").join().unwrap());
+    }
+
+    #[test]
+    fn case_panic_handler() {
+        let pool = ThreadPool::new(1);
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        pool.set_panic_handler(move |id, payload| {
+            let msg = payload.downcast_ref::<&str>().copied();
+            lock(&tx).send((id, msg)).unwrap();
+        });
+
+        pool.execute(|| panic!("first"));
+        pool.execute(|| panic!("second"));
+        assert_eq!((0, Some("first")), rx.recv().unwrap());
+        assert_eq!((0, Some("second")), rx.recv().unwrap());
+    }
+
+    #[test]
+    fn case_respawn() {
+        // the handler's panic kills the only worker, which must be
+        // replaced for the next job to run.
+        let pool = ThreadPool::new(1);
+        pool.set_panic_handler(|_, _| panic!("handler"));
+
+        pool.execute(|| panic!("job"));
+        assert_eq!("alive", pool.spawn(|| "alive").join().unwrap());
+        assert_eq!(1, lock(&pool.shared.workers).alive());
+    }
+
+    #[test]
+    fn case_listener_panics() {
+        // the first job is lost with the worker, but not counted as
+        // running forever
+        let panicked = AtomicBool::new(false);
+        let pool = ThreadPool::builder()
+            .size(1)
+            .listener(move |event: &Event| {
+                if matches!(event, Event::JobStarted { .. })
+                    && !panicked.swap(true, Ordering::SeqCst)
+                {
+                    panic!("listener");
                 }
-                Err(_) => {
-                    println!("Worker {id} disconnected; shutting down.");
-                    return;
+            })
+            .build()
+            .unwrap();
+
+        pool.execute(|| ());
+        assert_eq!("alive", pool.spawn(|| "alive").join().unwrap());
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while pool.stats().running != 0 && Instant::now() < deadline {
+            thread::yield_now();
+        }
+        assert_eq!(0, pool.stats().running);
+    }
+
+    #[test]
+    fn case_listener_panics_at_shutdown() {
+        let respawned = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&respawned);
+        let pool = ThreadPool::builder()
+            .size(2)
+            .listener(move |event: &Event| match event {
+                Event::WorkerExited { .. } => panic!("listener"),
+                Event::WorkerRespawned { .. } => {
+                    counter.fetch_add(1, Ordering::SeqCst);
                 }
-            }
+                _ => {}
+            })
+            .build()
+            .unwrap();
+
+        // no worker is replaced once the pool is closed, or each new
+        // one would exit and panic in turn
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            pool.shutdown();
+            tx.send(()).unwrap();
         });
-        Worker {
-            id,
-            thread: Some(handler),
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(0, respawned.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn case_stats_and_listener() {
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        let pool = ThreadPool::builder()
+            .size(1)
+            .listener(move |event: &Event| lock(&tx).send(format!("{event:?}")).unwrap())
+            .build()
+            .unwrap();
+
+        let (gate_tx, gate_rx) = mpsc::channel::<()>();
+        let blocked = pool.spawn(move || gate_rx.recv().unwrap());
+        assert_eq!("JobStarted { worker: 0 }", rx.recv().unwrap());
+        pool.execute(|| panic!("boom"));
+        assert_eq!(
+            Stats {
+                workers: 1,
+                queued: 1,
+                running: 1,
+                completed: 0,
+                panicked: 0,
+            },
+            pool.stats()
+        );
+
+        gate_tx.send(()).unwrap();
+        blocked.join().unwrap();
+        let events: Vec<_> = rx.iter().take(3).collect();
+        assert_eq!(
+            vec![
+                "JobCompleted { worker: 0 }",
+                "JobStarted { worker: 0 }",
+                "JobPanicked { worker: 0 }"
+            ],
+            events
+        );
+        assert_eq!(
+            Stats {
+                workers: 1,
+                queued: 0,
+                running: 0,
+                completed: 1,
+                panicked: 1,
+            },
+            pool.stats()
+        );
+
+        drop(pool);
+        let events: Vec<_> = rx.iter().collect();
+        assert_eq!(
+            vec!["ShutdownStarted", "WorkerExited { worker: 0 }"],
+            events
+        );
+    }
+
+    #[test]
+    fn case_bounded_queue() {
+        let pool = ThreadPool::builder()
+            .size(1)
+            .queue_capacity(2)
+            .build()
+            .unwrap();
+        let (tx, rx) = mpsc::channel::<()>();
+        let busy = pool.spawn(move || rx.recv().unwrap());
+        while pool.stats().running == 0 {
+            thread::yield_now();
+        }
+
+        let (done_tx, done_rx) = mpsc::channel();
+        for i in 0..2 {
+            let done_tx = done_tx.clone();
+            pool.try_execute(move || done_tx.send(i).unwrap()).unwrap();
+        }
+        assert!(pool.try_execute(|| ()).is_err());
+        let start = Instant::now();
+        let timeout = Duration::from_millis(50);
+        let err = pool.execute_timeout(|| (), timeout).unwrap_err();
+        assert!(start.elapsed() >= timeout);
+        assert_eq!("the pool's queue is full", err.to_string());
+
+        // a blocked execute goes on once the worker frees a slot
+        let blocked = thread::scope(|s| {
+            let blocked = s.spawn(|| pool.execute(move || done_tx.send(2).unwrap()));
+            thread::sleep(Duration::from_millis(20));
+            assert!(!blocked.is_finished());
+            tx.send(()).unwrap();
+            blocked.join()
+        });
+        assert!(blocked.is_ok());
+        busy.join().unwrap();
+        assert_eq!(vec![0, 1, 2], done_rx.iter().take(3).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn case_grow_and_retire() {
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        let pool = ThreadPool::builder()
+            .size(1)
+            .max_size(2)
+            .keep_alive(Duration::from_millis(20))
+            .listener(move |event: &Event| {
+                if let Event::WorkerSpawned { worker } | Event::WorkerRetired { worker } = event {
+                    lock(&tx).send(format!("{event:?}")).unwrap();
+                    assert_eq!(1, *worker);
+                }
+            })
+            .build()
+            .unwrap();
+
+        // the second job waits with the only worker busy
+        let (gate_tx, gate_rx) = mpsc::channel::<()>();
+        let busy = pool.spawn(move || gate_rx.recv().unwrap());
+        while pool.stats().running == 0 {
+            thread::yield_now();
         }
+        assert_eq!("ok", pool.spawn(|| "ok").join().unwrap());
+        assert_eq!("WorkerSpawned { worker: 1 }", rx.recv().unwrap());
+
+        // the extra worker retires, the core one stays
+        assert_eq!("WorkerRetired { worker: 1 }", rx.recv().unwrap());
+        assert_eq!(1, pool.stats().workers);
+        gate_tx.send(()).unwrap();
+        busy.join().unwrap();
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(1, pool.stats().workers);
+    }
+
+    #[test]
+    fn case_set_size() {
+        let pool = ThreadPool::builder()
+            .size(1)
+            .keep_alive(Duration::from_millis(10))
+            .build()
+            .unwrap();
+        assert!(matches!(pool.set_size(0), Err(PoolCreationError::ZeroSize)));
+
+        pool.set_size(3).unwrap();
+        assert_eq!(3, pool.stats().workers);
+        let handles: Vec<_> = (0..6).map(|i| pool.spawn(move || i)).collect();
+        let sum: i32 = handles.into_iter().map(|h| h.join().unwrap()).sum();
+        assert_eq!(15, sum);
+
+        // a fixed pool shrinks as its idle workers retire, and does
+        // not grow back under load
+        pool.set_size(1).unwrap();
+        while pool.stats().workers > 1 {
+            thread::sleep(Duration::from_millis(5));
+        }
+        let (gate_tx, gate_rx) = mpsc::channel::<()>();
+        let busy = pool.spawn(move || gate_rx.recv().unwrap());
+        pool.execute(|| ());
+        assert_eq!(1, pool.stats().workers);
+        gate_tx.send(()).unwrap();
+        busy.join().unwrap();
+    }
+
+    #[test]
+    fn case_shutdown() {
+        let pool = ThreadPool::new(2);
+        let (tx, rx) = mpsc::channel();
+        for i in 0..4 {
+            let tx = tx.clone();
+            pool.execute(move || tx.send(i).unwrap());
+        }
+        pool.shutdown();
+        drop(tx);
+        let mut done: Vec<_> = rx.iter().collect();
+        done.sort();
+        assert_eq!(vec![0, 1, 2, 3], done);
+    }
+
+    #[test]
+    fn case_shutdown_timeout() {
+        let pool = ThreadPool::new(1);
+        let (tx, rx) = mpsc::channel::<()>();
+        let stuck = pool.spawn(move || rx.recv().unwrap());
+        while pool.stats().running == 0 {
+            thread::yield_now();
+        }
+        let queued = pool.spawn(|| ());
+
+        let err = pool
+            .shutdown_timeout(Duration::from_millis(20))
+            .unwrap_err();
+        assert_eq!(&[0], err.workers());
+        assert_eq!(1, err.cancelled());
+        assert_eq!(
+            "1 workers did not finish in time, 1 queued jobs were dropped",
+            err.to_string()
+        );
+        assert!(queued.join().unwrap_err().is_cancelled());
+
+        // the detached worker still finishes its job
+        tx.send(()).unwrap();
+        assert!(stuck.join().is_ok());
+
+        let pool = ThreadPool::new(2);
+        pool.execute(|| ());
+        assert!(pool.shutdown_timeout(Duration::from_secs(5)).is_ok());
+    }
+
+    #[test]
+    fn case_shutdown_from_job() {
+        let pool = ThreadPool::new(2);
+        let (pool_tx, pool_rx) = mpsc::channel::<ThreadPool>();
+        let dropped = pool.spawn(move || drop(pool_rx.recv().unwrap()));
+        pool_tx.send(pool).unwrap();
+        dropped.join().unwrap();
+
+        let pool = ThreadPool::new(1);
+        let (pool_tx, pool_rx) = mpsc::channel::<ThreadPool>();
+        let stopped = pool.spawn(move || {
+            let pool = pool_rx.recv().unwrap();
+            pool.shutdown_timeout(Duration::from_secs(5))
+        });
+        pool_tx.send(pool).unwrap();
+        assert!(stopped.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn case_priority() {
+        let pool = ThreadPool::new(1);
+        let (gate_tx, gate_rx) = mpsc::channel::<()>();
+        let busy = pool.spawn(move || gate_rx.recv().unwrap());
+        while pool.stats().running == 0 {
+            thread::yield_now();
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let jobs = [
+            ("low", Priority::Low),
+            ("normal", Priority::Normal),
+            ("high", Priority::High),
+        ];
+        for (name, priority) in jobs {
+            let tx = tx.clone();
+            pool.execute_priority(move || tx.send(name).unwrap(), priority);
+        }
+        let high = pool.spawn_priority(|| "spawned", Priority::High);
+
+        gate_tx.send(()).unwrap();
+        busy.join().unwrap();
+        assert_eq!("spawned", high.join().unwrap());
+        let order: Vec<_> = rx.iter().take(3).collect();
+        assert_eq!(vec!["high", "normal", "low"], order);
+    }
+
+    #[test]
+    fn case_spawn_is_finished() {
+        let pool = ThreadPool::new(1);
+        let (tx, rx) = mpsc::channel::<()>();
+
+        let handle = pool.spawn(move || rx.recv().unwrap());
+        assert!(!handle.is_finished());
+        tx.send(()).unwrap();
+        handle.join().unwrap();
     }
 }
@@ -0,0 +1,126 @@
+//! `listener` reports what the pool is doing.
+//!
+//! The pool prints nothing by itself. Events go to the
+//! [`Listener`] set with [`Builder::listener`](crate::Builder::listener),
+//! and counters are read with [`ThreadPool::stats`](crate::ThreadPool::stats).
+
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// something that happened in the pool.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Event<'a> {
+    /// a worker took a job and is running it
+    JobStarted {
+        /// the worker's id
+        worker: usize,
+    },
+    /// a job returned normally
+    JobCompleted {
+        /// the worker's id
+        worker: usize,
+    },
+    /// a job panicked; the worker goes on
+    JobPanicked {
+        /// the worker's id
+        worker: usize,
+    },
+    /// a worker was added because jobs were waiting, or the pool
+    /// was resized
+    WorkerSpawned {
+        /// the worker's id
+        worker: usize,
+    },
+    /// an extra worker could not be added
+    WorkerSpawnFailed {
+        /// why the new thread could not be spawned
+        error: &'a io::Error,
+    },
+    /// a worker beyond the core size was idle too long and exited
+    WorkerRetired {
+        /// the worker's id
+        worker: usize,
+    },
+    /// a worker thread died and was replaced
+    WorkerRespawned {
+        /// the worker's id
+        worker: usize,
+    },
+    /// a worker thread died and could not be replaced
+    WorkerRespawnFailed {
+        /// the worker's id
+        worker: usize,
+        /// why the new thread could not be spawned
+        error: &'a io::Error,
+    },
+    /// a worker saw the pool shut down and exited
+    WorkerExited {
+        /// the worker's id
+        worker: usize,
+    },
+    /// the pool stopped taking jobs and waits for its workers
+    ShutdownStarted,
+}
+
+/// receives the pool's [`Event`]s.
+///
+/// It is called on the worker threads, so it should be quick.
+/// Any `Fn(&Event)` closure is a listener:
+///
+/// ```rust
+/// use threadpool::{Event, ThreadPool};
+///
+/// let pool = ThreadPool::builder()
+///     .size(2)
+///     .listener(|event: &Event| eprintln!("{event:?}"))
+///     .build()
+///     .unwrap();
+/// ```
+pub trait Listener: Send + Sync {
+    /// called for every event.
+    fn on_event(&self, event: &Event);
+}
+
+impl<F> Listener for F
+where
+    F: Fn(&Event) + Send + Sync,
+{
+    fn on_event(&self, event: &Event) {
+        self(event)
+    }
+}
+
+/// a snapshot of the pool's counters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Stats {
+    /// workers alive
+    pub workers: usize,
+    /// jobs waiting for a worker
+    pub queued: usize,
+    /// jobs being run right now
+    pub running: usize,
+    /// jobs that returned normally
+    pub completed: usize,
+    /// jobs that panicked
+    pub panicked: usize,
+}
+
+#[derive(Default)]
+pub(crate) struct Counters {
+    pub(crate) running: AtomicUsize,
+    pub(crate) completed: AtomicUsize,
+    pub(crate) panicked: AtomicUsize,
+}
+
+impl Counters {
+    pub(crate) fn snapshot(&self, workers: usize, queued: usize) -> Stats {
+        Stats {
+            workers,
+            queued,
+            running: self.running.load(Ordering::Relaxed),
+            completed: self.completed.load(Ordering::Relaxed),
+            panicked: self.panicked.load(Ordering::Relaxed),
+        }
+    }
+}
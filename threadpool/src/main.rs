@@ -3,47 +3,750 @@
 //!    A web server to serve http request
 //!
 //! EndPoint:
-//!    /         -> ("HTTP/1.1 200 OK", "hello.html")
-//!    /sleep    -> sleep 5 seconds and then ("HTTP/1.1 200 OK", "hello.html")
-//!    otherwise -> ("HTTP/1.1 404 NOT FOUND", "404.html")
+//!    GET /          -> (200, "hello.html")
+//!    GET /sleep     -> sleep 5 seconds and then (200, "hello.html"),
+//!                      at low priority so that it does not hold up `/`
+//!    GET /users/:id -> (200, the id)
+//!    GET /static/*  -> the file under `static/`, see `http::files`
+//!    HEAD on these paths -> the same headers, without the body
+//!    other methods on these paths -> 405 with the `Allow` header
+//!    otherwise      -> (404, "404.html")
+//!
+//!    A request that cannot be parsed gets a 4xx or 5xx response
+//!    saying why, see `http::ParseError`.
+//!
+//! Connection:
+//!    kept open for the next request, unless the client says
+//!    `Connection: close`, it stays idle for `IDLE_TIMEOUT`, or it
+//!    has made `MAX_REQUESTS`. An open connection holds a worker
+//!    while it waits, so at most one less than the workers are kept
+//!    open, and the others are closed after their response.
+//!    Pipelined requests are answered in order. `/sleep` answers
+//!    from its own job, which takes the connection over with the
+//!    requests read ahead.
+//!
+//! Settings:
+//!    the address, port, workers and the most connections open at
+//!    once, from a config file, the environment or the command line,
+//!    see `config` or `--help`.
+//!
+//! Shutdown:
+//!    on SIGINT or SIGTERM the server stops accepting connections
+//!    and closes the reading half of the open ones: the idle ones
+//!    end at once, and the requests under way are answered with
+//!    `Connection: close`. A connection still sending its response
+//!    after `SHUTDOWN_TIMEOUT` is cut, then the server exits once
+//!    the handlers left return. A second signal exits at once.
+
+mod config;
+mod http;
+mod signal;
 
 use std::{
+    env,
+    error::Error,
     fs,
-    io::{prelude::*, BufReader},
-    net::{TcpListener, TcpStream},
+    io::{self, BufRead, BufReader},
+    net::{Shutdown, TcpListener, TcpStream},
+    path::Path,
+    process,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex, MutexGuard, PoisonError,
+    },
 };
 
+use config::{Command, Config};
+use http::{Match, Params, Request, Response, Router};
 use std::thread;
-use std::time::Duration;
-use threadpool;
+use std::time::{Duration, Instant};
+use threadpool::{Priority, Scope, ThreadPool};
+
+/// the directory served under `/static/`.
+const STATIC_DIR: &str = "static";
+/// how long a connection may wait for its next request.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+/// how many requests a connection may make before it is closed.
+const MAX_REQUESTS: usize = 100;
+/// how long the jobs left at shutdown may take.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+type Handler = fn(&Request, &Params) -> Response;
+
+/// a handler, and the priority it runs at.
+struct Route {
+    handler: Handler,
+    priority: Priority,
+}
+
+fn routes() -> Router<Route> {
+    let route = |handler, priority| Route { handler, priority };
+    Router::new()
+        .route("GET", "/", route(index, Priority::Normal))
+        .route("GET", "/sleep", route(sleep, Priority::Low))
+        .route("GET", "/users/:id", route(user, Priority::Normal))
+        .route("GET", "/static/*path", route(static_file, Priority::Normal))
+}
 
 fn main() {
-    let listener = TcpListener::bind("127.0.0.1:80").unwrap();
-    let pool = threadpool::ThreadPool::new(4);
-    for stream in listener.incoming().take(6) {
-        let stream = stream.unwrap();
-        let task = || handle_connection(stream);
-        pool.execute(task);
+    let program = env::args()
+        .next()
+        .unwrap_or_else(|| "threadpool".to_string());
+    let config = match Command::parse(env::args(), |name| env::var(name).ok()) {
+        Ok(Command::Run(config)) => config,
+        Ok(Command::Help) => {
+            print!("{}", config::help(&program));
+            return;
+        }
+        Err(e) => {
+            eprintln!("{program}: {e}\nTry '{program} --help' for more information.");
+            process::exit(2);
+        }
+    };
+    if let Err(e) = run(&config) {
+        eprintln!("{program}: {e}");
+        process::exit(1);
     }
 }
 
-fn handle_connection(mut stream: TcpStream) {
-    let buf_reader = BufReader::new(&mut stream);
-    let request_line = buf_reader.lines().next().unwrap().unwrap();
-
-    let (status_line, filename) = match &request_line[..] {
-        "GET / HTTP/1.1" => ("HTTP/1.1 200 OK", "hello.html"),
-        "GET /sleep HTTP/1.1" => {
-            thread::sleep(Duration::from_secs(5));
-            ("HTTP/1.1 200 OK", "hello.html")
+fn run(config: &Config) -> Result<(), Box<dyn Error>> {
+    let address = (config.address.as_str(), config.port);
+    let listener = TcpListener::bind(address)
+        .map_err(|e| format!("failed to listen on {}:{}: {e}", address.0, address.1))?;
+    let local = listener.local_addr()?;
+    let stopping = Arc::new(AtomicBool::new(false));
+    let stop = Arc::clone(&stopping);
+    signal::handle(move |signal| {
+        if stop.swap(true, Ordering::SeqCst) {
+            process::exit(128 + signal);
         }
-        _ => ("HTTP/1.1 404 NOT FOUND", "404.html"),
+        println!("stopping on signal {signal}");
+        // wakes the accept loop up
+        let _ = TcpStream::connect(local);
+    })
+    .map_err(|e| format!("failed to handle signals: {e}"))?;
+
+    // every thread of the pool is spawned after the signals are
+    // blocked, so none of them takes one
+    let builder = ThreadPool::builder();
+    let pool = match config.workers {
+        Some(workers) => builder.size(workers),
+        None => builder.available_parallelism(),
+    }
+    .build()?;
+    println!("listening on {local}");
+
+    let server = Server {
+        stopping,
+        ..Server::new(routes(), pool.stats().workers, config.max_connections)
     };
+    server.listen(&pool, listener);
+
+    // the scope waited for the connections and their `/sleep` jobs,
+    // so no job is left
+    pool.shutdown();
+    Ok(())
+}
+
+/// what the connections share.
+struct Server {
+    router: Router<Route>,
+    /// the connections open
+    open: AtomicUsize,
+    /// the most connections open at once, the next get a 503
+    max_connections: Option<usize>,
+    /// the connections kept open after a response
+    kept: AtomicUsize,
+    /// one less than the workers, so that the connections waiting
+    /// for their next request never hold every worker
+    max_kept: usize,
+    /// how long a connection may wait for its next request
+    idle_timeout: Duration,
+    /// set once the server is asked to stop
+    stopping: Arc<AtomicBool>,
+    /// the open connections, closed at shutdown
+    streams: Streams,
+    /// how long the responses under way may take at shutdown
+    shutdown_timeout: Duration,
+}
+
+impl Server {
+    fn new(router: Router<Route>, workers: usize, max_connections: Option<usize>) -> Server {
+        Server {
+            router,
+            open: AtomicUsize::new(0),
+            max_connections,
+            kept: AtomicUsize::new(0),
+            max_kept: workers.saturating_sub(1),
+            idle_timeout: IDLE_TIMEOUT,
+            stopping: Arc::new(AtomicBool::new(false)),
+            streams: Streams::default(),
+            shutdown_timeout: SHUTDOWN_TIMEOUT,
+        }
+    }
+
+    fn is_stopping(&self) -> bool {
+        self.stopping.load(Ordering::SeqCst)
+    }
+
+    /// answers the connections on `listener` with the workers of
+    /// `pool`. Once `stopping` is set, the next connection wakes it
+    /// up, and it returns when the connections open are done, or cut
+    /// after `shutdown_timeout`.
+    fn listen(&self, pool: &ThreadPool, listener: TcpListener) {
+        pool.scope(|s| {
+            for stream in listener.incoming() {
+                if self.is_stopping() {
+                    break;
+                }
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        eprintln!("failed to accept a connection: {e}");
+                        continue;
+                    }
+                };
+                let Some(slot) = Slot::take(&self.open, self.max_connections) else {
+                    let response =
+                        Response::error(503, "too many connections").header("Retry-After", "1");
+                    let _ = send(&stream, response, false);
+                    continue;
+                };
+                match Connection::new(self, stream, slot) {
+                    Ok(connection) => {
+                        s.spawn(move || serve(s, self, connection));
+                    }
+                    Err(e) => eprintln!("failed to set up a connection: {e}"),
+                }
+            }
+            // refuses new connections while the open ones finish
+            drop(listener);
+            self.streams.drain(self.shutdown_timeout);
+        });
+    }
+}
+
+/// counts an open connection until it is dropped.
+struct Slot<'a>(&'a AtomicUsize);
+
+impl<'a> Slot<'a> {
+    /// takes a slot, unless `max` connections are already open.
+    fn take(open: &'a AtomicUsize, max: Option<usize>) -> Option<Slot<'a>> {
+        let max = max.unwrap_or(usize::MAX);
+        open.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+            (n < max).then_some(n + 1)
+        })
+        .ok()?;
+        Some(Slot(open))
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
 
-    let contents = fs::read_to_string(filename).unwrap();
-    let length = contents.len();
+/// the streams of the open connections.
+#[derive(Default)]
+struct Streams {
+    streams: Mutex<Vec<(usize, TcpStream)>>,
+    next_id: AtomicUsize,
+    /// notified when a connection closes
+    closed: Condvar,
+}
+
+impl Streams {
+    /// lists a clone of `stream` until the returned entry is dropped.
+    fn add(&self, stream: &TcpStream) -> io::Result<Listed<'_>> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let clone = stream.try_clone()?;
+        lock(&self.streams).push((id, clone));
+        Ok(Listed { streams: self, id })
+    }
+
+    /// Closes the reading half of the streams, which ends those
+    /// waiting for a request, and waits for the others to send their
+    /// response. Those still open after `timeout` are cut.
+    fn drain(&self, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        let mut streams = lock(&self.streams);
+        for (_, stream) in streams.iter() {
+            let _ = stream.shutdown(Shutdown::Read);
+        }
+        while !streams.is_empty() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                for (_, stream) in streams.iter() {
+                    let _ = stream.shutdown(Shutdown::Both);
+                }
+                return;
+            }
+            let result = self.closed.wait_timeout(streams, left);
+            streams = result.unwrap_or_else(PoisonError::into_inner).0;
+        }
+    }
+}
 
-    let response = format!("{status_line}\r\nContent-Length: {length}\r\n\r\n{contents}");
+/// keeps a connection in [`Streams`] while it is open.
+struct Listed<'a> {
+    streams: &'a Streams,
+    id: usize,
+}
 
-    stream.write_all(response.as_bytes()).unwrap();
+impl Drop for Listed<'_> {
+    fn drop(&mut self) {
+        lock(&self.streams.streams).retain(|(id, _)| *id != self.id);
+        self.streams.closed.notify_all();
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// a connection, and the requests read ahead on it.
+struct Connection<'a> {
+    stream: TcpStream,
+    /// keeps the pipelined requests read along with one
+    reader: BufReader<TcpStream>,
+    served: usize,
+    _slot: Slot<'a>,
+    _listed: Listed<'a>,
+    /// taken once the connection is first kept open
+    kept: Option<Slot<'a>>,
+}
+
+impl<'a> Connection<'a> {
+    fn new(server: &'a Server, stream: TcpStream, slot: Slot<'a>) -> io::Result<Connection<'a>> {
+        stream.set_read_timeout(Some(server.idle_timeout))?;
+        Ok(Connection {
+            reader: BufReader::new(stream.try_clone()?),
+            _listed: server.streams.add(&stream)?,
+            stream,
+            served: 0,
+            _slot: slot,
+            kept: None,
+        })
+    }
+
+    /// returns true if the connection may stay open after this
+    /// response, taking a keep-alive slot if it has none yet.
+    fn keep(&mut self, server: &'a Server) -> bool {
+        if self.kept.is_none() {
+            self.kept = Slot::take(&server.kept, Some(server.max_kept));
+        }
+        self.kept.is_some()
+    }
+
+    /// reads the next request, answering it if it is malformed.
+    /// Returns `None` once the connection is to be closed.
+    fn next_request(&mut self) -> Option<Request> {
+        // the client closed the connection, or left it idle too long
+        if !matches!(self.reader.fill_buf(), Ok(buf) if !buf.is_empty()) {
+            return None;
+        }
+        match Request::read(&mut self.reader) {
+            Ok(request) => request,
+            Err(e) => {
+                let response = Response::error(e.status(), &e.to_string());
+                let _ = send(&self.stream, response, false);
+                None
+            }
+        }
+    }
+}
+
+/// answers the requests on `connection` until it is closed.
+///
+/// A route run at another priority takes the connection along to
+/// its own job, which then goes on with the requests after it.
+fn serve<'scope, 'env>(
+    s: &'scope Scope<'scope, 'env>,
+    server: &'env Server,
+    mut connection: Connection<'env>,
+) {
+    while connection.served < MAX_REQUESTS {
+        let Some(request) = connection.next_request() else {
+            return;
+        };
+        connection.served += 1;
+        match &request.query {
+            Some(query) => println!("{} {}?{query}", request.method, request.path),
+            None => println!("{} {}", request.method, request.path),
+        }
+
+        let keep_alive = request.keep_alive()
+            && connection.served < MAX_REQUESTS
+            && !server.is_stopping()
+            && connection.keep(server);
+        let head = request.method == "HEAD";
+        let response = match server.router.find(&request.method, &request.path) {
+            Match::Found(route, params) if route.priority == Priority::Normal => {
+                (route.handler)(&request, &params)
+            }
+            Match::Found(route, params) => {
+                let handler = route.handler;
+                let task = move || {
+                    let mut response = handler(&request, &params);
+                    if head {
+                        response = response.without_body();
+                    }
+                    let keep_alive = keep_alive && !server.is_stopping();
+                    if send(&connection.stream, response, keep_alive).is_ok() && keep_alive {
+                        serve(s, server, connection);
+                    }
+                };
+                s.spawn_priority(task, route.priority);
+                return;
+            }
+            Match::MethodNotAllowed(allow) => {
+                let message = format!("{} is not allowed here", request.method);
+                Response::error(405, &message).header("Allow", &allow.join(", "))
+            }
+            Match::NotFound => page(404, "404.html"),
+        };
+        let response = if head {
+            response.without_body()
+        } else {
+            response
+        };
+        // the server may have been asked to stop while the handler ran
+        let keep_alive = keep_alive && !server.is_stopping();
+        if send(&connection.stream, response, keep_alive).is_err() || !keep_alive {
+            return;
+        }
+    }
+}
+
+/// writes `response`, telling the client whether the connection
+/// stays open.
+fn send(mut stream: &TcpStream, response: Response, keep_alive: bool) -> io::Result<()> {
+    let connection = if keep_alive { "keep-alive" } else { "close" };
+    response
+        .header("Connection", connection)
+        .write_to(&mut stream)
+}
+
+fn index(_: &Request, _: &Params) -> Response {
+    page(200, "hello.html")
+}
+
+fn sleep(_: &Request, _: &Params) -> Response {
+    thread::sleep(Duration::from_secs(5));
+    page(200, "hello.html")
+}
+
+fn user(_: &Request, params: &Params) -> Response {
+    let id = params.get("id").unwrap_or_default();
+    Response::new(200)
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .body(format!("user {id}\n"))
+}
+
+fn static_file(request: &Request, params: &Params) -> Response {
+    let path = params.get("path").unwrap_or_default();
+    http::files::serve(Path::new(STATIC_DIR), request, path)
+}
+
+/// a response with the html page `filename`.
+fn page(status: u16, filename: &str) -> Response {
+    match fs::read(filename) {
+        Ok(contents) => Response::new(status)
+            .header("Content-Type", "text/html; charset=utf-8")
+            .body(contents),
+        Err(e) => Response::error(500, &format!("failed to read {filename}: {e}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::SocketAddr;
+
+    /// the names of the `/wait/:name` requests started, and of those
+    /// which may return.
+    static STARTED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    static RELEASED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    fn router() -> Router<Route> {
+        let route = |handler, priority| Route { handler, priority };
+        Router::new()
+            .route("GET", "/users/:id", route(user, Priority::Normal))
+            .route("GET", "/wait/:name", route(wait, Priority::Normal))
+            .route("GET", "/wait-low/:name", route(wait, Priority::Low))
+            .route("GET", "/big/:name", route(big, Priority::Normal))
+    }
+
+    /// answers once `release(name)` is called.
+    fn wait(_: &Request, params: &Params) -> Response {
+        let name = params.get("name").unwrap_or_default().to_string();
+        lock(&STARTED).push(name.clone());
+        while !lock(&RELEASED).contains(&name) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        Response::new(200).body(name)
+    }
+
+    /// a body larger than the socket buffers.
+    fn big(_: &Request, params: &Params) -> Response {
+        let name = params.get("name").unwrap_or_default().to_string();
+        lock(&STARTED).push(name);
+        Response::new(200).body(vec![b'x'; 64 << 20])
+    }
+
+    /// waits until the handler of the request `name` has started.
+    fn started(name: &str) {
+        while !lock(&STARTED).iter().any(|started| started == name) {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn release(name: &str) {
+        lock(&RELEASED).push(name.to_string());
+    }
+
+    /// a server with `workers` workers, closing idle connections
+    /// after a second.
+    fn server(workers: usize) -> Server {
+        Server {
+            idle_timeout: Duration::from_secs(1),
+            ..Server::new(router(), workers, None)
+        }
+    }
+
+    /// a server listening on a port of its own until dropped.
+    struct Running {
+        server: Arc<Server>,
+        local: SocketAddr,
+        thread: Option<thread::JoinHandle<()>>,
+    }
+
+    impl Running {
+        fn start(server: Server, workers: usize) -> Running {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let local = listener.local_addr().unwrap();
+            let server = Arc::new(server);
+            let thread = {
+                let server = Arc::clone(&server);
+                thread::spawn(move || server.listen(&ThreadPool::new(workers), listener))
+            };
+            Running {
+                server,
+                local,
+                thread: Some(thread),
+            }
+        }
+
+        fn connect(&self) -> Client {
+            let stream = TcpStream::connect(self.local).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(10)))
+                .unwrap();
+            Client {
+                reader: BufReader::new(stream.try_clone().unwrap()),
+                stream,
+            }
+        }
+
+        /// asks the server to stop, as a signal does.
+        fn signal(&self) {
+            self.server.stopping.store(true, Ordering::SeqCst);
+            let _ = TcpStream::connect(self.local);
+        }
+
+        /// stops the server and waits until it is done.
+        fn stop(&mut self) {
+            if let Some(thread) = self.thread.take() {
+                self.signal();
+                thread.join().unwrap();
+            }
+        }
+    }
+
+    impl Drop for Running {
+        fn drop(&mut self) {
+            self.stop();
+        }
+    }
+
+    struct Client {
+        stream: TcpStream,
+        reader: BufReader<TcpStream>,
+    }
+
+    impl Client {
+        fn send(&mut self, raw: &str) {
+            self.stream.write_all(raw.as_bytes()).unwrap();
+        }
+
+        /// reads the next response, head and body, or `None` once
+        /// the server closed the connection.
+        fn response(&mut self) -> Option<String> {
+            let mut response = String::new();
+            loop {
+                let mut line = String::new();
+                match self.reader.read_line(&mut line) {
+                    Ok(0) => return None,
+                    Err(e) if e.kind() == io::ErrorKind::ConnectionReset => return None,
+                    result => result.unwrap(),
+                };
+                response.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let len = header(&response, "Content-Length").map_or(0, |len| len.parse().unwrap());
+            let mut body = vec![0; len];
+            self.reader.read_exact(&mut body).unwrap();
+            response.push_str(&String::from_utf8(body).unwrap());
+            Some(response)
+        }
+    }
+
+    fn get(path: &str) -> String {
+        format!("GET {path} HTTP/1.1\r\nHost: a\r\n\r\n")
+    }
+
+    /// the value of header `name` in `response`.
+    fn header<'a>(response: &'a str, name: &str) -> Option<&'a str> {
+        let head = response.split("\r\n\r\n").next().unwrap();
+        head.lines()
+            .filter_map(|line| line.split_once(": "))
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
+
+    #[test]
+    fn case_pipelined() {
+        let server = Running::start(server(2), 2);
+        let mut client = server.connect();
+        client.send(&format!("{}{}", get("/users/a"), get("/users/b")));
+
+        for id in ["a", "b"] {
+            let response = client.response().unwrap();
+            assert!(response.ends_with(&format!("user {id}\n")), "{response}");
+            assert_eq!(Some("keep-alive"), header(&response, "Connection"));
+        }
+    }
+
+    #[test]
+    fn case_close() {
+        let server = Running::start(server(2), 2);
+
+        let mut client = server.connect();
+        client.send("GET /users/a HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n");
+        let response = client.response().unwrap();
+        assert_eq!(Some("close"), header(&response, "Connection"));
+        assert_eq!(None, client.response());
+
+        // closed after its last request, even if the client goes on
+        let mut client = server.connect();
+        client.send(&get("/users/a").repeat(MAX_REQUESTS));
+        for i in 1..=MAX_REQUESTS {
+            let response = client.response().unwrap();
+            let expected = if i < MAX_REQUESTS {
+                "keep-alive"
+            } else {
+                "close"
+            };
+            assert_eq!(Some(expected), header(&response, "Connection"), "{i}");
+        }
+        assert_eq!(None, client.response());
+    }
+
+    #[test]
+    fn case_idle_timeout() {
+        let idle_timeout = Duration::from_millis(100);
+        let server = Running::start(
+            Server {
+                idle_timeout,
+                ..server(2)
+            },
+            2,
+        );
+        let mut client = server.connect();
+        client.send(&get("/users/a"));
+        assert!(client.response().is_some());
+
+        let idle = Instant::now();
+        assert_eq!(None, client.response());
+        assert!(idle.elapsed() >= idle_timeout);
+    }
+
+    #[test]
+    fn case_max_kept() {
+        // one connection may be kept open: the other worker is left
+        // for the next connections
+        let server = Running::start(server(2), 2);
+        let mut kept = server.connect();
+        kept.send(&get("/users/a"));
+        let response = kept.response().unwrap();
+        assert_eq!(Some("keep-alive"), header(&response, "Connection"));
+
+        for id in ["b", "c"] {
+            let mut client = server.connect();
+            client.send(&get(&format!("/users/{id}")));
+            let response = client.response().unwrap();
+            assert!(response.ends_with(&format!("user {id}\n")), "{response}");
+            assert_eq!(Some("close"), header(&response, "Connection"));
+        }
+
+        kept.send(&get("/users/d"));
+        assert!(kept.response().unwrap().ends_with("user d\n"));
+    }
+
+    #[test]
+    fn case_stop_mid_request() {
+        let server = Running::start(server(2), 2);
+        let mut inline = server.connect();
+        inline.send(&get("/wait/stop-inline"));
+        let mut offloaded = server.connect();
+        offloaded.send(&get("/wait-low/stop-offloaded"));
+        started("stop-inline");
+        started("stop-offloaded");
+
+        server.server.stopping.store(true, Ordering::SeqCst);
+        release("stop-inline");
+        release("stop-offloaded");
+        for client in [&mut inline, &mut offloaded] {
+            let response = client.response().unwrap();
+            assert_eq!(Some("close"), header(&response, "Connection"));
+            assert_eq!(None, client.response());
+        }
+    }
+
+    #[test]
+    fn case_shutdown() {
+        let mut server = Running::start(
+            Server {
+                idle_timeout: Duration::from_secs(60),
+                shutdown_timeout: Duration::from_millis(200),
+                ..Server::new(router(), 3, None)
+            },
+            3,
+        );
+        let mut idle = server.connect();
+        idle.send(&get("/users/a"));
+        assert!(idle.response().is_some());
+        let mut busy = server.connect();
+        busy.send(&get("/wait/shutdown"));
+        // never reads its response, so the server blocks sending it
+        let mut stuck = server.connect();
+        stuck.send(&get("/big/shutdown-stuck"));
+        started("shutdown");
+        started("shutdown-stuck");
+
+        let stopping = Instant::now();
+        server.signal();
+        // the idle connection is closed without waiting for its timeout
+        assert_eq!(None, idle.response());
+        release("shutdown");
+        let response = busy.response().unwrap();
+        assert_eq!(Some("close"), header(&response, "Connection"));
+        // and the stuck one is cut
+        server.stop();
+        assert!(stopping.elapsed() < Duration::from_secs(10));
+    }
 }
@@ -0,0 +1,521 @@
+//! `queue` holds the jobs waiting for a worker.
+//!
+//! Every worker owns a deque of jobs. Jobs queued from outside the
+//! pool go to a shared injector instead; jobs queued by a job go to
+//! the deque of the worker running it. A worker looks for a job
+//!
+//! 1. at the front of its own deque,
+//! 2. in the injector, moving a batch of jobs to its own deque so
+//!    the injector's lock is taken less often,
+//! 3. at the back of the other workers' deques, taking half of the
+//!    jobs found there ("work stealing").
+//!
+//! A job queued with [`Priority::High`] is taken before all of
+//! those, and one with [`Priority::Low`] only when none of them is
+//! left. Both wait in a lane of their own, shared by all workers.
+//!
+//! Each deque has its own lock and no lock is held while waiting,
+//! so workers rarely contend with each other. When no job is found
+//! the worker sleeps until one is queued, or until its idle timeout
+//! if it may retire.
+
+use crate::{lock, Job};
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// the most jobs a worker moves from the injector at once.
+const BATCH: usize = 32;
+
+/// how long a push may wait for room in a full queue.
+#[derive(Clone, Copy)]
+pub(crate) enum Wait {
+    No,
+    Until(Instant),
+    Forever,
+    /// never waits: the job is queued even past the capacity, for
+    /// pushes that a worker must not block on
+    Over,
+}
+
+/// how soon a job should run, relative to the other queued jobs.
+///
+/// Priorities only order the queue: a running job is never
+/// interrupted, and a low priority job may wait forever while
+/// others keep coming.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Priority {
+    /// before any normal job, e.g. for latency-sensitive work
+    High,
+    /// the priority of [`ThreadPool::execute`](crate::ThreadPool::execute)
+    #[default]
+    Normal,
+    /// only when no other job is queued, e.g. for batch work
+    Low,
+}
+
+/// the jobs of one priority other than normal.
+#[derive(Default)]
+struct Lane {
+    jobs: Mutex<VecDeque<Job>>,
+    /// lets workers skip the lock while the lane is empty
+    len: AtomicUsize,
+}
+
+impl Lane {
+    fn push(&self, job: Job) {
+        lock(&self.jobs).push_back(job);
+        self.len.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn pop(&self) -> Option<Job> {
+        if self.len.load(Ordering::SeqCst) == 0 {
+            return None;
+        }
+        let job = lock(&self.jobs).pop_front()?;
+        self.len.fetch_sub(1, Ordering::SeqCst);
+        Some(job)
+    }
+
+    fn clear(&self) -> VecDeque<Job> {
+        let jobs = std::mem::take(&mut *lock(&self.jobs));
+        self.len.fetch_sub(jobs.len(), Ordering::SeqCst);
+        jobs
+    }
+}
+
+/// what a worker gets from [`Queue::pop`].
+pub(crate) enum Pop {
+    Job(Job),
+    Closed,
+    TimedOut,
+}
+
+thread_local! {
+    /// the key of the queue and the deque of the worker running on
+    /// this thread.
+    static CURRENT: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+}
+
+/// the key of the next queue.
+static NEXT_KEY: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) struct Queue {
+    /// tells this queue apart in `CURRENT`. Not its address: a
+    /// detached worker may outlive its queue, and a new queue may
+    /// then take the same address.
+    key: usize,
+    high: Lane,
+    injector: Mutex<VecDeque<Job>>,
+    low: Lane,
+    /// the workers' deques, by worker id; only ever grows
+    locals: RwLock<Vec<Mutex<VecDeque<Job>>>>,
+    /// the number of jobs in the injector and all deques
+    len: AtomicUsize,
+    capacity: Option<usize>,
+    sleep: Mutex<bool>,
+    /// signalled when a job is queued or the queue is closed
+    wake: Condvar,
+    /// signalled when a job is taken from a bounded queue
+    not_full: Condvar,
+    sleepers: AtomicUsize,
+    blocked: AtomicUsize,
+}
+
+impl Queue {
+    pub(crate) fn new(workers: usize, capacity: Option<usize>) -> Queue {
+        Queue {
+            key: NEXT_KEY.fetch_add(1, Ordering::Relaxed),
+            high: Lane::default(),
+            injector: Mutex::new(VecDeque::new()),
+            low: Lane::default(),
+            locals: RwLock::new((0..workers).map(|_| Mutex::new(VecDeque::new())).collect()),
+            len: AtomicUsize::new(0),
+            capacity,
+            sleep: Mutex::new(false),
+            wake: Condvar::new(),
+            not_full: Condvar::new(),
+            sleepers: AtomicUsize::new(0),
+            blocked: AtomicUsize::new(0),
+        }
+    }
+
+    /// the number of jobs waiting for a worker.
+    pub(crate) fn len(&self) -> usize {
+        self.len.load(Ordering::SeqCst)
+    }
+
+    /// the number of workers waiting for a job.
+    pub(crate) fn sleepers(&self) -> usize {
+        self.sleepers.load(Ordering::SeqCst)
+    }
+
+    /// makes room for the deques of workers `0..workers`.
+    pub(crate) fn add_locals(&self, workers: usize) {
+        if self.read_locals().len() >= workers {
+            return;
+        }
+        let mut locals = self.locals.write().unwrap_or_else(PoisonError::into_inner);
+        while locals.len() < workers {
+            locals.push(Mutex::new(VecDeque::new()));
+        }
+    }
+
+    fn read_locals(&self) -> std::sync::RwLockReadGuard<'_, Vec<Mutex<VecDeque<Job>>>> {
+        self.locals.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// marks the calling thread as worker `id`, so that the jobs
+    /// it queues go to its own deque.
+    pub(crate) fn register(&self, id: usize) {
+        CURRENT.with(|current| current.set(Some((self.key, id))));
+    }
+
+    fn sleep_lock(&self) -> MutexGuard<'_, bool> {
+        lock(&self.sleep)
+    }
+
+    /// Queues `item` once there is room, turning it into a job only
+    /// then, so that it can be handed back if the wait times out.
+    pub(crate) fn push<T>(
+        &self,
+        item: T,
+        into_job: impl FnOnce(T) -> Job,
+        wait: Wait,
+        priority: Priority,
+    ) -> Result<(), T> {
+        if !self.reserve(wait) {
+            return Err(item);
+        }
+
+        let job = into_job(item);
+        let current = CURRENT.with(Cell::get);
+        match (priority, current) {
+            (Priority::High, _) => self.high.push(job),
+            (Priority::Low, _) => self.low.push(job),
+            (Priority::Normal, Some((key, id))) if key == self.key => {
+                lock(&self.read_locals()[id]).push_back(job)
+            }
+            (Priority::Normal, _) => lock(&self.injector).push_back(job),
+        }
+
+        // pairs with the check in `pop`: either the sleeper sees
+        // the new length, or we see the sleeper and wake it.
+        if self.sleepers.load(Ordering::SeqCst) > 0 {
+            let _sleep = self.sleep_lock();
+            self.wake.notify_one();
+        }
+        Ok(())
+    }
+
+    /// counts one more job, waiting for room if the queue is bounded.
+    fn reserve(&self, wait: Wait) -> bool {
+        let Some(capacity) = self.capacity.filter(|_| !matches!(wait, Wait::Over)) else {
+            self.len.fetch_add(1, Ordering::SeqCst);
+            return true;
+        };
+
+        loop {
+            let len = self.len.load(Ordering::SeqCst);
+            if len < capacity {
+                if self
+                    .len
+                    .compare_exchange(len, len + 1, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+                {
+                    return true;
+                }
+                continue;
+            }
+
+            let sleep = self.sleep_lock();
+            // a closed queue will not make room for long, if ever
+            if *sleep {
+                return false;
+            }
+            self.blocked.fetch_add(1, Ordering::SeqCst);
+            if self.len.load(Ordering::SeqCst) < capacity {
+                self.blocked.fetch_sub(1, Ordering::SeqCst);
+                continue;
+            }
+            let waited = match wait {
+                Wait::No | Wait::Over => false,
+                Wait::Forever => {
+                    drop(
+                        self.not_full
+                            .wait(sleep)
+                            .unwrap_or_else(PoisonError::into_inner),
+                    );
+                    true
+                }
+                Wait::Until(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(left) if !left.is_zero() => {
+                        let result = self.not_full.wait_timeout(sleep, left);
+                        drop(result.unwrap_or_else(PoisonError::into_inner));
+                        true
+                    }
+                    _ => false,
+                },
+            };
+            self.blocked.fetch_sub(1, Ordering::SeqCst);
+            if !waited {
+                return false;
+            }
+        }
+    }
+
+    /// Waits for the next job for worker `id`, or until the queue is
+    /// closed and empty. Before sleeping the worker asks `idle` how
+    /// long it may stay idle; `None` means forever.
+    pub(crate) fn pop(&self, id: usize, idle: impl Fn() -> Option<Duration>) -> Pop {
+        let mut deadline = None;
+        loop {
+            if let Some(job) = self.find(id) {
+                self.len.fetch_sub(1, Ordering::SeqCst);
+                if self.blocked.load(Ordering::SeqCst) > 0 {
+                    let _sleep = self.sleep_lock();
+                    self.not_full.notify_one();
+                }
+                return Pop::Job(job);
+            }
+
+            let closed = self.sleep_lock();
+            self.sleepers.fetch_add(1, Ordering::SeqCst);
+            if self.len.load(Ordering::SeqCst) > 0 {
+                // a job is queued but not visible yet, or another
+                // worker is about to take it; look again.
+                self.sleepers.fetch_sub(1, Ordering::SeqCst);
+                drop(closed);
+                thread::yield_now();
+                continue;
+            }
+            if *closed {
+                self.sleepers.fetch_sub(1, Ordering::SeqCst);
+                return Pop::Closed;
+            }
+            match idle() {
+                None => {
+                    deadline = None;
+                    drop(
+                        self.wake
+                            .wait(closed)
+                            .unwrap_or_else(PoisonError::into_inner),
+                    );
+                }
+                Some(timeout) => {
+                    let deadline = *deadline.get_or_insert_with(|| Instant::now() + timeout);
+                    match deadline.checked_duration_since(Instant::now()) {
+                        Some(left) if !left.is_zero() => {
+                            let result = self.wake.wait_timeout(closed, left);
+                            drop(result.unwrap_or_else(PoisonError::into_inner));
+                        }
+                        _ => {
+                            self.sleepers.fetch_sub(1, Ordering::SeqCst);
+                            return Pop::TimedOut;
+                        }
+                    }
+                }
+            }
+            self.sleepers.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    fn find(&self, id: usize) -> Option<Job> {
+        if let Some(job) = self.high.pop() {
+            return Some(job);
+        }
+
+        let locals = self.read_locals();
+        let local = &locals[id];
+        if let Some(job) = lock(local).pop_front() {
+            return Some(job);
+        }
+
+        let mut injector = lock(&self.injector);
+        if let Some(job) = injector.pop_front() {
+            let n = (injector.len() / locals.len()).min(BATCH);
+            if n > 0 {
+                lock(local).extend(injector.drain(..n));
+            }
+            return Some(job);
+        }
+        drop(injector);
+
+        for other in (1..locals.len()).map(|i| (id + i) % locals.len()) {
+            let mut victim = lock(&locals[other]);
+            let n = victim.len().div_ceil(2);
+            if n == 0 {
+                continue;
+            }
+            let at = victim.len() - n;
+            let mut stolen = victim.split_off(at);
+            drop(victim);
+            let job = stolen.pop_front();
+            if !stolen.is_empty() {
+                lock(local).extend(stolen);
+            }
+            return job;
+        }
+        drop(locals);
+
+        self.low.pop()
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        *self.sleep_lock()
+    }
+
+    /// Stops the workers once the queued jobs are done, and turns
+    /// away the pushes waiting for room.
+    pub(crate) fn close(&self) {
+        *self.sleep_lock() = true;
+        self.wake.notify_all();
+        self.not_full.notify_all();
+    }
+
+    /// Drops the jobs still queued and returns how many there were.
+    pub(crate) fn clear(&self) -> usize {
+        let mut jobs: Vec<Job> = self.high.clear().into();
+        jobs.extend(lock(&self.injector).drain(..));
+        for local in self.read_locals().iter() {
+            jobs.extend(lock(local).drain(..));
+        }
+        jobs.extend(self.low.clear());
+        self.len.fetch_sub(jobs.len(), Ordering::SeqCst);
+        if self.blocked.load(Ordering::SeqCst) > 0 {
+            let _sleep = self.sleep_lock();
+            self.not_full.notify_all();
+        }
+        // drop the jobs outside of the locks: a job's drop may
+        // wake the thread waiting for its result
+        let n = jobs.len();
+        drop(jobs);
+        n
+    }
+
+    /// Wakes every sleeping worker, so that each asks again how
+    /// long it may stay idle.
+    pub(crate) fn wake_all(&self) {
+        let _sleep = self.sleep_lock();
+        self.wake.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Outcome;
+    use std::sync::Arc;
+
+    fn pop(queue: &Queue, id: usize) -> Option<Job> {
+        match queue.pop(id, || None) {
+            Pop::Job(job) => Some(job),
+            _ => None,
+        }
+    }
+
+    fn job(tx: &std::sync::mpsc::Sender<usize>, i: usize) -> Job {
+        let tx = tx.clone();
+        Box::new(move || {
+            tx.send(i).unwrap();
+            Outcome::Completed
+        })
+    }
+
+    #[test]
+    fn case_steal() {
+        let queue = Queue::new(2, None);
+        let (tx, rx) = std::sync::mpsc::channel();
+
+        // worker 0 moves a batch from the injector to its deque
+        for i in 0..5 {
+            queue
+                .push(i, |i| job(&tx, i), Wait::No, Priority::Normal)
+                .ok()
+                .unwrap();
+        }
+        pop(&queue, 0).unwrap()();
+        assert_eq!(2, lock(&queue.read_locals()[0]).len());
+        assert_eq!(2, lock(&queue.injector).len());
+
+        // worker 1 drains the injector, then steals from worker 0
+        for _ in 0..2 {
+            pop(&queue, 1).unwrap()();
+        }
+        pop(&queue, 1).unwrap()();
+        assert_eq!(1, lock(&queue.read_locals()[0]).len());
+        pop(&queue, 0).unwrap()();
+        assert_eq!(0, queue.len());
+
+        let mut order: Vec<_> = rx.try_iter().collect();
+        order.sort();
+        assert_eq!(vec![0, 1, 2, 3, 4], order);
+    }
+
+    #[test]
+    fn case_priority() {
+        let queue = Queue::new(1, None);
+        let (tx, rx) = std::sync::mpsc::channel();
+
+        let priorities = [Priority::Low, Priority::Normal, Priority::High];
+        for (i, priority) in priorities.into_iter().enumerate() {
+            queue
+                .push(i, |i| job(&tx, i), Wait::No, priority)
+                .ok()
+                .unwrap();
+        }
+        while queue.len() > 0 {
+            pop(&queue, 0).unwrap()();
+        }
+        assert_eq!(vec![2, 1, 0], rx.try_iter().collect::<Vec<_>>());
+        assert_eq!(0, queue.low.len.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn case_local_push_and_close() {
+        let queue = Arc::new(Queue::new(2, None));
+        let (tx, rx) = std::sync::mpsc::channel();
+
+        let worker = {
+            let queue = Arc::clone(&queue);
+            thread::spawn(move || {
+                queue.register(1);
+                queue
+                    .push(7, |i| job(&tx, i), Wait::No, Priority::Normal)
+                    .ok()
+                    .unwrap();
+                assert_eq!(1, lock(&queue.read_locals()[1]).len());
+                while let Some(job) = pop(&queue, 1) {
+                    job();
+                }
+            })
+        };
+
+        assert_eq!(7, rx.recv().unwrap());
+        queue.close();
+        worker.join().unwrap();
+    }
+
+    #[test]
+    fn case_outlived_queue() {
+        // a thread still registered with a dropped queue pushes to
+        // the injector of the next, even at the same address
+        let old = Box::new(Queue::new(8, None));
+        old.register(7);
+        drop(old);
+
+        let queue = Box::new(Queue::new(1, None));
+        let (tx, rx) = std::sync::mpsc::channel();
+        queue
+            .push(1, |i| job(&tx, i), Wait::No, Priority::Normal)
+            .ok()
+            .unwrap();
+        assert_eq!(1, lock(&queue.injector).len());
+        pop(&queue, 0).unwrap()();
+        assert_eq!(1, rx.recv().unwrap());
+        CURRENT.with(|current| current.set(None));
+    }
+}
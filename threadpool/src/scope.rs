@@ -0,0 +1,224 @@
+//! `scope` runs jobs which borrow from the caller's stack.
+//!
+//! [`ThreadPool::scope`] does not return before every job spawned in
+//! it is done, so the jobs may hold references to anything that
+//! outlives the scope, as with [`std::thread::scope`].
+
+use crate::join::{self, JoinHandle};
+use crate::queue::{Priority, Wait};
+use crate::{lock, Job, Outcome, ThreadPool};
+use std::marker::PhantomData;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+
+/// spawns jobs which may borrow data living for `'env`.
+///
+/// Created by [`ThreadPool::scope`].
+pub struct Scope<'scope, 'env: 'scope> {
+    pool: &'scope ThreadPool,
+    pending: Arc<Pending>,
+    /// invariant over both lifetimes, as in `std::thread::Scope`
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+/// the number of jobs of a scope not done yet.
+#[derive(Default)]
+struct Pending {
+    count: Mutex<usize>,
+    done: Condvar,
+}
+
+impl Pending {
+    fn wait(&self) {
+        let mut count = lock(&self.count);
+        while *count > 0 {
+            count = self
+                .done
+                .wait(count)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+}
+
+/// counts a job as done when dropped, whether it ran or not.
+struct Guard(Arc<Pending>);
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let mut count = lock(&self.0.count);
+        *count -= 1;
+        if *count == 0 {
+            self.0.done.notify_all();
+        }
+    }
+}
+
+/// a scoped job. Its fields drop in order, so the guard counts it
+/// done only after everything it borrows has been let go.
+struct Scoped<F> {
+    f: F,
+    _guard: Guard,
+}
+
+impl<'scope, 'env> Scope<'scope, 'env> {
+    /// Runs `f` on the pool like [`ThreadPool::spawn`], but `f` may
+    /// borrow from outside the scope.
+    ///
+    /// A panic in `f` is returned by the handle's
+    /// [`join`](JoinHandle::join); it does not reach the scope.
+    pub fn spawn<F, T>(&'scope self, f: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope,
+    {
+        self.spawn_priority(f, Priority::Normal)
+    }
+
+    /// Runs `f` like [`spawn`](Scope::spawn), with the given
+    /// `priority` as in [`ThreadPool::execute_priority`].
+    pub fn spawn_priority<F, T>(&'scope self, f: F, priority: Priority) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope,
+    {
+        *lock(&self.pending.count) += 1;
+        let scoped = Scoped {
+            f,
+            _guard: Guard(Arc::clone(&self.pending)),
+        };
+
+        let (handle, completer) = join::pair();
+        let job: Box<dyn FnOnce() -> Outcome + Send + 'scope> = Box::new(move || {
+            // moves the whole job in, not just `f`, so the guard
+            // drops once `f` has run
+            let scoped = scoped;
+            completer.run(scoped.f)
+        });
+        // SAFETY: `ThreadPool::scope` waits for the guard of every job
+        // before `'scope` ends, so the job never outlives what it
+        // borrows. Only the lifetime of the trait object changes.
+        let job: Job = unsafe { mem::transmute(job) };
+        let shared = &self.pool.shared;
+        let _ = shared.send(job, |job| job, Wait::Forever, priority);
+        handle
+    }
+}
+
+impl ThreadPool {
+    /// Creates a scope for jobs that borrow local data.
+    ///
+    /// Every job spawned in the scope has finished when `scope`
+    /// returns, even if `f` panics.
+    ///
+    /// ```rust
+    /// let pool = threadpool::ThreadPool::new(2);
+    /// let words = vec!["hello", "world"];
+    /// let mut lengths = vec![0; words.len()];
+    ///
+    /// pool.scope(|s| {
+    ///     for (word, len) in words.iter().zip(&mut lengths) {
+    ///         s.spawn(move || *len = word.len());
+    ///     }
+    /// });
+    /// assert_eq!(vec![5, 5], lengths);
+    /// ```
+    ///
+    /// Waiting for the jobs blocks the calling thread. Called from a
+    /// job of the same pool, it may wait forever if every worker is
+    /// blocked the same way.
+    pub fn scope<'env, F, R>(&self, f: F) -> R
+    where
+        F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> R,
+    {
+        let scope = Scope {
+            pool: self,
+            pending: Arc::new(Pending::default()),
+            scope: PhantomData,
+            env: PhantomData,
+        };
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+        scope.pending.wait();
+        match result {
+            Ok(result) => result,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn case_scope() {
+        let pool = ThreadPool::new(2);
+        let counter = AtomicUsize::new(0);
+        let mut data = vec![1, 2, 3];
+
+        let sum = pool.scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    thread::sleep(Duration::from_millis(5));
+                    counter.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+            let sum = s.spawn(|| data.iter().sum::<i32>());
+            sum.join().unwrap()
+        });
+        assert_eq!(6, sum);
+        assert_eq!(4, counter.load(Ordering::SeqCst));
+
+        // the borrow has ended, so `data` can be changed again
+        pool.scope(|s| {
+            s.spawn(|| data.push(4));
+        });
+        assert_eq!(vec![1, 2, 3, 4], data);
+    }
+
+    #[test]
+    fn case_scope_priority() {
+        let pool = ThreadPool::new(1);
+        let order = Mutex::new(Vec::new());
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+
+        pool.scope(|s| {
+            // holds the only worker until both jobs are queued
+            s.spawn(move || rx.recv().unwrap());
+            s.spawn_priority(|| lock(&order).push("low"), Priority::Low);
+            s.spawn(|| lock(&order).push("normal"));
+            tx.send(()).unwrap();
+        });
+        assert_eq!(vec!["normal", "low"], order.into_inner().unwrap());
+    }
+
+    #[test]
+    fn case_scope_panic() {
+        let pool = ThreadPool::new(1);
+        let done = AtomicUsize::new(0);
+
+        // a job's panic goes to its handle
+        pool.scope(|s| {
+            let handle = s.spawn(|| panic!("job"));
+            s.spawn(|| done.fetch_add(1, Ordering::SeqCst));
+            assert!(handle.join().unwrap_err().is_panic());
+        });
+
+        // the scope's own panic waits for the jobs first
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            pool.scope(|s| {
+                s.spawn(|| {
+                    thread::sleep(Duration::from_millis(20));
+                    done.fetch_add(1, Ordering::SeqCst);
+                });
+                panic!("scope");
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(2, done.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,47 @@
+//! `signal` hands SIGINT and SIGTERM to a thread of their own.
+//!
+//! The signals are blocked in every thread and taken one at a time
+//! with `sigwait`, so the callback is plain code rather than a
+//! signal handler and may lock, allocate or connect a socket. Only
+//! Linux is supported; elsewhere the signals keep their default
+//! action and kill the process.
+
+use std::io;
+
+/// Calls `on_signal` with the signal number each time SIGINT or
+/// SIGTERM arrives.
+///
+/// The signals are blocked in the calling thread and in the threads
+/// it spawns afterwards, so this must be called before any other
+/// thread is spawned.
+#[cfg(target_os = "linux")]
+pub fn handle(on_signal: impl Fn(i32) + Send + 'static) -> io::Result<()> {
+    // SAFETY: `sigset_t` is valid once `sigemptyset` initialized it.
+    let set = unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGINT);
+        libc::sigaddset(&mut set, libc::SIGTERM);
+        let rc = libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+        if rc != 0 {
+            return Err(io::Error::from_raw_os_error(rc));
+        }
+        set
+    };
+
+    std::thread::Builder::new()
+        .name("signal".to_string())
+        .spawn(move || loop {
+            let mut signal = 0;
+            // SAFETY: as above
+            if unsafe { libc::sigwait(&set, &mut signal) } == 0 {
+                on_signal(signal);
+            }
+        })?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn handle(_on_signal: impl Fn(i32) + Send + 'static) -> io::Result<()> {
+    Ok(())
+}
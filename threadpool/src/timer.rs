@@ -0,0 +1,459 @@
+//! `timer` queues jobs on the pool once they are due.
+//!
+//! A single thread, started by the first delayed job, sleeps until
+//! the earliest one is due and then hands it to the workers. It
+//! never runs a job itself, so a slow job cannot hold up the
+//! others.
+
+use crate::queue::{Priority, Wait};
+use crate::{into_job, lock, Shared, ThreadPool};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::io;
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub(crate) struct Timer {
+    state: Mutex<State>,
+    /// signalled when an entry is added or the timer is closed
+    wake: Condvar,
+    thread: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+#[derive(Default)]
+struct State {
+    entries: BinaryHeap<Reverse<Entry>>,
+    /// orders the entries due at the same instant by when they
+    /// were scheduled
+    seq: u64,
+    closed: bool,
+}
+
+struct Entry {
+    at: Instant,
+    seq: u64,
+    cancelled: Arc<AtomicBool>,
+    task: Task,
+}
+
+enum Task {
+    Once(Box<dyn FnOnce() + Send>),
+    Every(Arc<Periodic>),
+}
+
+struct Periodic {
+    f: Box<dyn Fn() + Send + Sync>,
+    period: Duration,
+    /// set while a run is queued or running, so that a slow run
+    /// makes the next ones skip instead of piling up
+    running: AtomicBool,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Entry) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Entry) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Entry) -> Ordering {
+        (self.at, self.seq).cmp(&(other.at, other.seq))
+    }
+}
+
+impl Timer {
+    pub(crate) fn new() -> Timer {
+        Timer {
+            state: Mutex::new(State::default()),
+            wake: Condvar::new(),
+            thread: Mutex::new(None),
+        }
+    }
+
+    /// runs `task` at `at`, starting the timer thread if needed.
+    fn schedule(&self, shared: &Arc<Shared>, at: Instant, task: Task) -> io::Result<TimerHandle> {
+        let handle = TimerHandle {
+            cancelled: Arc::new(AtomicBool::new(false)),
+        };
+        let mut thread = lock(&self.thread);
+        if thread.is_none() {
+            let shared = Arc::clone(shared);
+            let builder = shared.thread.timer_builder();
+            *thread = Some(builder.spawn(move || shared.timer.run(&shared))?);
+        }
+        drop(thread);
+
+        self.insert(at, Arc::clone(&handle.cancelled), task);
+        Ok(handle)
+    }
+
+    /// adds an entry, or cancels it if the timer is closed.
+    fn insert(&self, at: Instant, cancelled: Arc<AtomicBool>, task: Task) {
+        let mut state = lock(&self.state);
+        if state.closed {
+            cancelled.store(true, atomic::Ordering::SeqCst);
+            return;
+        }
+        let seq = state.seq;
+        state.seq += 1;
+        state.entries.push(Reverse(Entry {
+            at,
+            seq,
+            cancelled,
+            task,
+        }));
+        self.wake.notify_one();
+    }
+
+    /// drops the entries not due yet and tells the timer thread to
+    /// stop.
+    pub(crate) fn close(&self) {
+        let entries = {
+            let mut state = lock(&self.state);
+            state.closed = true;
+            std::mem::take(&mut state.entries)
+        };
+        self.wake.notify_one();
+        for Reverse(entry) in entries {
+            entry.cancelled.store(true, atomic::Ordering::SeqCst);
+        }
+    }
+
+    /// waits for the timer thread to stop. The thread may be waiting
+    /// for room in a full queue, so close the queue first.
+    pub(crate) fn join(&self) {
+        let thread = lock(&self.thread).take();
+        if let Some(thread) = thread {
+            let _ = thread.join();
+        }
+    }
+
+    fn run(&self, shared: &Arc<Shared>) {
+        while let Some(entry) = self.next() {
+            if entry.cancelled.load(atomic::Ordering::SeqCst) {
+                continue;
+            }
+            match entry.task {
+                Task::Once(f) => {
+                    let cancelled = entry.cancelled;
+                    let job = move || {
+                        if !cancelled.load(atomic::Ordering::SeqCst) {
+                            f();
+                        }
+                    };
+                    let _ = shared.send(job, into_job, Wait::Forever, Priority::Normal);
+                }
+                Task::Every(periodic) => {
+                    self.queue_run(shared, &periodic, &entry.cancelled);
+
+                    // keeps the beat, but skips the runs already missed;
+                    // a run too far off for `Instant` is never due
+                    let now = Instant::now();
+                    let at = match entry.at.checked_add(periodic.period) {
+                        Some(at) if at > now => Some(at),
+                        _ => now.checked_add(periodic.period),
+                    };
+                    if let Some(at) = at {
+                        self.insert(at, entry.cancelled, Task::Every(periodic));
+                    }
+                }
+            }
+        }
+    }
+
+    fn queue_run(
+        &self,
+        shared: &Arc<Shared>,
+        periodic: &Arc<Periodic>,
+        cancelled: &Arc<AtomicBool>,
+    ) {
+        if periodic.running.swap(true, atomic::Ordering::SeqCst) {
+            return;
+        }
+        let periodic = Arc::clone(periodic);
+        let cancelled = Arc::clone(cancelled);
+        let job = move || {
+            // clears the flag even if `f` panics
+            let _running = Running(&periodic.running);
+            if !cancelled.load(atomic::Ordering::SeqCst) {
+                (periodic.f)();
+            }
+        };
+        let _ = shared.send(job, into_job, Wait::Forever, Priority::Normal);
+    }
+
+    /// waits for the next entry to be due. Returns `None` once the
+    /// timer is closed.
+    fn next(&self) -> Option<Entry> {
+        let mut state = lock(&self.state);
+        loop {
+            if state.closed {
+                return None;
+            }
+            let now = Instant::now();
+            match state.entries.peek() {
+                Some(Reverse(entry)) if entry.at <= now => {
+                    return state.entries.pop().map(|Reverse(entry)| entry);
+                }
+                Some(Reverse(entry)) => {
+                    let left = entry.at - now;
+                    let result = self.wake.wait_timeout(state, left);
+                    state = result.unwrap_or_else(PoisonError::into_inner).0;
+                }
+                None => {
+                    state = self
+                        .wake
+                        .wait(state)
+                        .unwrap_or_else(PoisonError::into_inner);
+                }
+            }
+        }
+    }
+}
+
+struct Running<'a>(&'a AtomicBool);
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, atomic::Ordering::SeqCst);
+    }
+}
+
+/// cancels a job queued with [`ThreadPool::execute_after`] or
+/// [`ThreadPool::execute_every`].
+///
+/// Dropping the handle does not cancel the job.
+#[derive(Debug, Clone)]
+pub struct TimerHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl TimerHandle {
+    /// Stops the job from running again. A run already started
+    /// goes on to its end.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, atomic::Ordering::SeqCst);
+    }
+
+    /// returns true once the job was cancelled, or the pool shut
+    /// down before it was due.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(atomic::Ordering::SeqCst)
+    }
+}
+
+impl ThreadPool {
+    /// Queues `f` once `delay` has passed.
+    ///
+    /// A job not due yet when the pool shuts down never runs.
+    ///
+    /// # Panics
+    ///
+    /// It panics if the timer thread, started by the first delayed
+    /// job, cannot be spawned.
+    ///
+    /// ```rust
+    /// use std::sync::mpsc;
+    /// use std::time::Duration;
+    ///
+    /// let pool = threadpool::ThreadPool::new(1);
+    /// let (tx, rx) = mpsc::channel();
+    ///
+    /// let tx2 = tx.clone();
+    /// pool.execute_after(Duration::from_millis(20), move || tx2.send("late").unwrap());
+    /// pool.execute_after(Duration::from_millis(10), move || tx.send("early").unwrap());
+    /// assert_eq!(vec!["early", "late"], rx.iter().take(2).collect::<Vec<_>>());
+    /// ```
+    pub fn execute_after<F>(&self, delay: Duration, f: F) -> TimerHandle
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        // a delay too long to represent is never due
+        let at = Instant::now().checked_add(delay);
+        let task = Task::Once(Box::new(f));
+        match at {
+            Some(at) => self.schedule(at, task),
+            None => TimerHandle {
+                cancelled: Arc::new(AtomicBool::new(false)),
+            },
+        }
+    }
+
+    /// Queues `f` every `period`, the first time one `period` from
+    /// now, until the handle cancels it or the pool shuts down.
+    ///
+    /// If a run is still queued or running when the next one is
+    /// due, that one is skipped. A run further off than an
+    /// [`Instant`] can reach never comes.
+    ///
+    /// # Panics
+    ///
+    /// It panics if `period` is zero, or if the timer thread cannot
+    /// be spawned.
+    ///
+    /// ```rust
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// let pool = threadpool::ThreadPool::new(1);
+    /// let ticks = Arc::new(AtomicUsize::new(0));
+    ///
+    /// let counter = Arc::clone(&ticks);
+    /// let handle = pool.execute_every(Duration::from_millis(5), move || {
+    ///     counter.fetch_add(1, Ordering::SeqCst);
+    /// });
+    /// while ticks.load(Ordering::SeqCst) < 3 {}
+    /// handle.cancel();
+    /// ```
+    pub fn execute_every<F>(&self, period: Duration, f: F) -> TimerHandle
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        assert!(!period.is_zero(), "the period must be greater than zero");
+        let periodic = Periodic {
+            f: Box::new(f),
+            period,
+            running: AtomicBool::new(false),
+        };
+        let task = Task::Every(Arc::new(periodic));
+        match Instant::now().checked_add(period) {
+            Some(at) => self.schedule(at, task),
+            None => TimerHandle {
+                cancelled: Arc::new(AtomicBool::new(false)),
+            },
+        }
+    }
+
+    fn schedule(&self, at: Instant, task: Task) -> TimerHandle {
+        match self.shared.timer.schedule(&self.shared, at, task) {
+            Ok(handle) => handle,
+            Err(e) => panic!("failed to spawn the timer thread: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn case_execute_after() {
+        let pool = ThreadPool::new(2);
+        let (tx, rx) = mpsc::channel();
+
+        for (name, delay) in [("c", 30), ("a", 10), ("b", 20)] {
+            let tx = tx.clone();
+            let delay = Duration::from_millis(delay);
+            pool.execute_after(delay, move || tx.send(name).unwrap());
+        }
+        let cancelled = {
+            let tx = tx.clone();
+            pool.execute_after(Duration::from_millis(5), move || tx.send("x").unwrap())
+        };
+        cancelled.cancel();
+        assert!(cancelled.is_cancelled());
+
+        drop(tx);
+        assert_eq!(vec!["a", "b", "c"], rx.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn case_execute_every() {
+        let pool = ThreadPool::new(1);
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+
+        let handle = pool.execute_every(Duration::from_millis(5), move || {
+            lock(&tx).send(()).unwrap();
+        });
+        for _ in 0..3 {
+            rx.recv().unwrap();
+        }
+        handle.cancel();
+
+        // at most a run already queued may still come
+        thread::sleep(Duration::from_millis(20));
+        let late = rx.try_iter().count();
+        thread::sleep(Duration::from_millis(20));
+        assert!(late <= 1);
+        assert_eq!(0, rx.try_iter().count());
+    }
+
+    #[test]
+    fn case_execute_every_long_period() {
+        let pool = ThreadPool::new(1);
+        let (tx, rx) = mpsc::channel();
+
+        // due now, but its next run is past the end of `Instant`
+        let periodic = Periodic {
+            f: Box::new(move || tx.send(()).unwrap()),
+            period: Duration::MAX,
+            running: AtomicBool::new(false),
+        };
+        let task = Task::Every(Arc::new(periodic));
+        pool.schedule(Instant::now(), task);
+        rx.recv().unwrap();
+
+        // the timer thread is still there for the others
+        let (tx, rx) = mpsc::channel();
+        pool.execute_after(Duration::from_millis(1), move || tx.send(()).unwrap());
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        // never due at all, but accepted as well
+        let never = pool.execute_every(Duration::MAX, || {});
+        assert!(!never.is_cancelled());
+    }
+
+    #[test]
+    fn case_timer_shutdown() {
+        let pool = ThreadPool::new(1);
+        let (tx, rx) = mpsc::channel::<()>();
+        let handle = pool.execute_after(Duration::from_secs(60), move || tx.send(()).unwrap());
+
+        // the pending job is dropped with its sender
+        pool.shutdown();
+        assert!(rx.recv().is_err());
+        assert!(handle.is_cancelled());
+    }
+
+    #[test]
+    fn case_timer_shutdown_full_queue() {
+        let pool = ThreadPool::builder()
+            .size(1)
+            .queue_capacity(1)
+            .build()
+            .unwrap();
+        let (gate, wait) = mpsc::channel::<()>();
+        pool.execute(move || wait.recv().unwrap());
+        // the worker is busy, so this one fills the queue
+        thread::sleep(Duration::from_millis(20));
+        pool.execute(|| {});
+
+        // the timer thread blocks on the full queue with this one
+        let (tx, rx) = mpsc::channel::<()>();
+        pool.execute_after(Duration::from_millis(1), move || tx.send(()).unwrap());
+        thread::sleep(Duration::from_millis(50));
+
+        let start = Instant::now();
+        let err = pool
+            .shutdown_timeout(Duration::from_millis(50))
+            .unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(&[0], err.workers());
+        gate.send(()).unwrap();
+        // the delayed job was turned away, not run
+        assert!(rx.recv().is_err());
+    }
+}
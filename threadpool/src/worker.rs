@@ -0,0 +1,272 @@
+//! `worker` runs jobs taken from the pool's queue.
+//!
+//! The pool keeps at least `core` workers. When jobs wait and no
+//! worker is idle, it spawns more, up to `max`. A worker beyond
+//! `core` which has been idle for `keep_alive` retires.
+
+use crate::affinity;
+use crate::queue::Pop;
+use crate::{lock, Event, Outcome, Shared};
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+pub(crate) struct Worker {
+    thread: thread::JoinHandle<()>,
+    /// set once the worker saw the pool shut down, so that joining
+    /// it does not block for long
+    done: bool,
+}
+
+impl Worker {
+    fn new(id: usize, shared: Arc<Shared>) -> io::Result<Worker> {
+        let core = shared.thread.core(id);
+        let (pinned_tx, pinned) = mpsc::sync_channel(1);
+        let handler = shared.thread.builder(id).spawn(move || {
+            if let Some(core) = core {
+                let result = affinity::pin(core);
+                let failed = result.is_err();
+                let _ = pinned_tx.send(result);
+                if failed {
+                    return;
+                }
+            }
+            let sentinel = Sentinel { id, shared };
+            sentinel.run();
+        })?;
+        if core.is_some() {
+            if let Ok(Err(e)) = pinned.recv() {
+                let _ = handler.join();
+                return Err(e);
+            }
+        }
+        Ok(Worker {
+            thread: handler,
+            done: false,
+        })
+    }
+
+    #[cfg(test)]
+    pub(crate) fn thread(&self) -> &thread::Thread {
+        self.thread.thread()
+    }
+}
+
+/// the workers of a pool, by id, and how many there should be.
+///
+/// A free id is reused by the next worker, so ids stay below
+/// `max`, as do the worker deques in the queue.
+pub(crate) struct Workers {
+    pub(crate) slots: Vec<Option<Worker>>,
+    pub(crate) core: usize,
+    pub(crate) max: usize,
+}
+
+impl Workers {
+    pub(crate) fn new(core: usize, max: usize) -> Workers {
+        Workers {
+            slots: Vec::with_capacity(max),
+            core,
+            max,
+        }
+    }
+
+    pub(crate) fn alive(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.is_some()).count()
+    }
+
+    /// the id of the worker running on the calling thread, if any.
+    pub(crate) fn current(&self) -> Option<usize> {
+        let me = thread::current().id();
+        self.slots.iter().position(|slot| {
+            slot.as_ref()
+                .is_some_and(|worker| worker.thread.thread().id() == me)
+        })
+    }
+
+    /// starts a worker in the lowest free slot and returns its id.
+    pub(crate) fn spawn(&mut self, shared: &Arc<Shared>) -> io::Result<usize> {
+        let id = match self.slots.iter().position(Option::is_none) {
+            Some(id) => id,
+            None => {
+                self.slots.push(None);
+                self.slots.len() - 1
+            }
+        };
+        shared.queue.add_locals(id + 1);
+
+        let worker = Worker::new(id, Arc::clone(shared));
+        if worker.is_err() && id + 1 == self.slots.len() {
+            self.slots.pop();
+        }
+        self.slots[id] = Some(worker?);
+        self.sync(shared);
+        Ok(id)
+    }
+
+    /// takes the thread of a worker which is done, to join it.
+    pub(crate) fn take_done(&mut self, shared: &Shared) -> Option<thread::JoinHandle<()>> {
+        let slot = self
+            .slots
+            .iter_mut()
+            .find(|slot| slot.as_ref().is_some_and(|worker| worker.done))?;
+        let worker = slot.take()?;
+        self.sync(shared);
+        Some(worker.thread)
+    }
+
+    /// lets the remaining workers go on without the pool, and
+    /// returns their ids.
+    pub(crate) fn detach(&mut self, shared: &Shared) -> Vec<usize> {
+        let ids = (0..self.slots.len())
+            .filter(|&id| self.slots[id].take().is_some())
+            .collect();
+        self.sync(shared);
+        ids
+    }
+
+    /// publishes the sizes for the checks which skip the lock.
+    pub(crate) fn sync(&self, shared: &Shared) {
+        shared.alive.store(self.alive(), Ordering::SeqCst);
+        shared.core.store(self.core, Ordering::SeqCst);
+        shared.max.store(self.max, Ordering::SeqCst);
+    }
+}
+
+/// lives on the worker's stack and replaces the worker if its
+/// thread unwinds, so the pool keeps its size until it shuts down.
+struct Sentinel {
+    id: usize,
+    shared: Arc<Shared>,
+}
+
+impl Sentinel {
+    fn run(&self) {
+        let id = self.id;
+        let shared = &self.shared;
+        let counters = &shared.counters;
+        shared.queue.register(id);
+        loop {
+            let job = match shared.queue.pop(id, || self.idle_timeout()) {
+                Pop::Job(job) => job,
+                Pop::Closed => {
+                    shared.emit(Event::WorkerExited { worker: id });
+                    self.done();
+                    return;
+                }
+                Pop::TimedOut => {
+                    if self.retire() {
+                        return;
+                    }
+                    continue;
+                }
+            };
+
+            let running = Running::start(&counters.running);
+            shared.emit(Event::JobStarted { worker: id });
+
+            let outcome = panic::catch_unwind(AssertUnwindSafe(job))
+                .unwrap_or_else(|payload| Outcome::Panicked(Some(payload)));
+
+            drop(running);
+            match outcome {
+                Outcome::Completed => {
+                    counters.completed.fetch_add(1, Ordering::Relaxed);
+                    shared.emit(Event::JobCompleted { worker: id });
+                }
+                Outcome::Pending => {}
+                Outcome::Panicked(payload) => {
+                    counters.panicked.fetch_add(1, Ordering::Relaxed);
+                    shared.emit(Event::JobPanicked { worker: id });
+                    let handler = lock(&shared.panic_handler).clone();
+                    if let (Some(handler), Some(payload)) = (handler, payload) {
+                        handler(id, payload);
+                    }
+                }
+            }
+        }
+    }
+
+    /// how long this worker may sleep before it should retire, if
+    /// the pool has more workers than its core size.
+    fn idle_timeout(&self) -> Option<Duration> {
+        let shared = &self.shared;
+        let extra = shared.alive.load(Ordering::SeqCst) > shared.core.load(Ordering::SeqCst);
+        extra.then_some(shared.keep_alive)
+    }
+
+    /// tells a pool shutting down that this worker can be joined.
+    fn done(&self) {
+        let mut workers = lock(&self.shared.workers);
+        if let Some(worker) = &mut workers.slots[self.id] {
+            worker.done = true;
+        }
+        self.shared.exited.notify_all();
+    }
+
+    fn retire(&self) -> bool {
+        let mut workers = lock(&self.shared.workers);
+        if workers.alive() <= workers.core {
+            return false;
+        }
+        // dropping the handle detaches this thread, which ends now
+        workers.slots[self.id] = None;
+        workers.sync(&self.shared);
+        drop(workers);
+        self.shared.emit(Event::WorkerRetired { worker: self.id });
+        true
+    }
+}
+
+/// counts a job as running while it lives, so that the count goes
+/// back down even if the listener unwinds before the job runs.
+struct Running<'a>(&'a AtomicUsize);
+
+impl Running<'_> {
+    fn start(count: &AtomicUsize) -> Running<'_> {
+        count.fetch_add(1, Ordering::Relaxed);
+        Running(count)
+    }
+}
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Sentinel {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            // a pool shutting down needs no new worker, and one would
+            // die in turn if the listener panics as workers exit
+            if self.shared.queue.is_closed() {
+                self.done();
+                return;
+            }
+            let worker = self.id;
+            // locked first, so that the new worker cannot mark this
+            // slot done before it holds the new worker
+            let mut workers = lock(&self.shared.workers);
+            match Worker::new(worker, Arc::clone(&self.shared)) {
+                Ok(new) => {
+                    workers.slots[worker] = Some(new);
+                    drop(workers);
+                    self.shared.emit(Event::WorkerRespawned { worker });
+                }
+                Err(error) => {
+                    workers.slots[worker] = None;
+                    workers.sync(&self.shared);
+                    self.shared.exited.notify_all();
+                    drop(workers);
+                    let error = &error;
+                    self.shared
+                        .emit(Event::WorkerRespawnFailed { worker, error });
+                }
+            }
+        }
+    }
+}